@@ -0,0 +1,56 @@
+//! Verification of recoverable secp256k1 signatures against a blake160
+//! pubkey hash, the scheme of the default CKB lock.
+//!
+//! With it, a cobuild lock guarded by a pubkey hash only needs to load its
+//! args and call `cobuild_entry(Secp256k1Blake160Verifier::new(pubkey_hash))`.
+use ckb_hash::blake2b_256;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, Secp256k1,
+};
+
+use crate::{error::Error, utils::ct_eq, Callback};
+
+/// Verifies that `seal` is a 65-byte recoverable secp256k1 signature of
+/// `signing_message_hash`, made by the key whose blake2b hash starts with
+/// `pubkey_hash`. Any failure is reported as `Error::AuthError`.
+pub fn verify_secp256k1_blake160(
+    pubkey_hash: [u8; 20],
+    seal: &[u8],
+    signing_message_hash: &[u8; 32],
+) -> Result<(), Error> {
+    if seal.len() != 65 {
+        return Err(Error::AuthError);
+    }
+    let recid = RecoveryId::from_i32(seal[64] as i32).map_err(|_| Error::AuthError)?;
+    let signature =
+        RecoverableSignature::from_compact(&seal[0..64], recid).map_err(|_| Error::AuthError)?;
+
+    let secp = Secp256k1::new();
+    let public_key = secp
+        .recover_ecdsa(&Message::from_digest(*signing_message_hash), &signature)
+        .map_err(|_| Error::AuthError)?;
+
+    let recovered_pubkey_hash = blake2b_256(public_key.serialize().as_slice());
+    if !ct_eq(&pubkey_hash, &recovered_pubkey_hash[0..20]) {
+        return Err(Error::AuthError);
+    }
+    Ok(())
+}
+
+/// A `Callback` verifying every seal with `verify_secp256k1_blake160`.
+pub struct Secp256k1Blake160Verifier {
+    pub pubkey_hash: [u8; 20],
+}
+
+impl Secp256k1Blake160Verifier {
+    pub fn new(pubkey_hash: [u8; 20]) -> Self {
+        Self { pubkey_hash }
+    }
+}
+
+impl Callback for Secp256k1Blake160Verifier {
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        verify_secp256k1_blake160(self.pubkey_hash, seal, signing_message_hash)
+    }
+}
@@ -1,70 +1,160 @@
+#[cfg(feature = "hash-trace")]
+use alloc::vec::Vec;
 use ckb_hash::{Blake2b, Blake2bBuilder};
 pub use molecule::lazy_reader::Cursor;
 
+use crate::error::{Error, LazyReaderError};
+
 pub const PERSONALIZATION_SIGHASH_ALL: &[u8] = b"ckb-tcob-sighash";
 pub const PERSONALIZATION_SIGHASH_ALL_ONLY: &[u8] = b"ckb-tcob-sgohash";
 pub const PERSONALIZATION_OTX: &[u8] = b"ckb-tcob-otxhash";
+pub const PERSONALIZATION_DIGEST: &[u8] = b"ckb-tcob-digest";
 
 const BATCH_SIZE: usize = 2048;
 
+// With the `paranoid` feature, the signing message hashes are checked at
+// compile time to use distinct personalizations, so a hash of one kind can't
+// be replayed as another.
+#[cfg(feature = "paranoid")]
+const _: () = {
+    const fn same(a: &[u8], b: &[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        let mut i = 0;
+        while i < a.len() {
+            if a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+    assert!(!same(PERSONALIZATION_OTX, PERSONALIZATION_SIGHASH_ALL));
+    assert!(!same(PERSONALIZATION_OTX, PERSONALIZATION_SIGHASH_ALL_ONLY));
+    assert!(!same(
+        PERSONALIZATION_SIGHASH_ALL,
+        PERSONALIZATION_SIGHASH_ALL_ONLY
+    ));
+};
+
+/// return a 32-byte blake2b instance with the given personalization, which
+/// must be at most 16 bytes
+pub fn new_blake2b_with_personal(personal: &[u8]) -> Blake2bStatistics {
+    Blake2bStatistics::new(Blake2bBuilder::new(32).personal(personal).build())
+}
+
 /// return a blake2b instance with personalization for SighashAll
 pub fn new_sighash_all_blake2b() -> Blake2bStatistics {
-    Blake2bStatistics::new(
-        Blake2bBuilder::new(32)
-            .personal(PERSONALIZATION_SIGHASH_ALL)
-            .build(),
-    )
+    new_blake2b_with_personal(PERSONALIZATION_SIGHASH_ALL)
 }
 
 /// return a blake2b instance with personalization for SighashAllOnly
 pub fn new_sighash_all_only_blake2b() -> Blake2bStatistics {
-    Blake2bStatistics::new(
-        Blake2bBuilder::new(32)
-            .personal(PERSONALIZATION_SIGHASH_ALL_ONLY)
-            .build(),
-    )
+    new_blake2b_with_personal(PERSONALIZATION_SIGHASH_ALL_ONLY)
 }
 
 /// return a blake2b instance with personalization for OTX
 pub fn new_otx_blake2b() -> Blake2bStatistics {
-    Blake2bStatistics::new(
-        Blake2bBuilder::new(32)
-            .personal(PERSONALIZATION_OTX)
-            .build(),
-    )
+    new_blake2b_with_personal(PERSONALIZATION_OTX)
 }
 
 pub struct Blake2bStatistics {
     count: usize,
     blake2b: Blake2b,
+    #[cfg(feature = "hash-trace")]
+    trace: Vec<usize>,
 }
 
 impl Blake2bStatistics {
     pub fn new(blake2b: Blake2b) -> Self {
-        Self { count: 0, blake2b }
+        Self {
+            count: 0,
+            blake2b,
+            #[cfg(feature = "hash-trace")]
+            trace: Vec::new(),
+        }
     }
 
     pub fn update(&mut self, data: &[u8]) {
-        self.blake2b.update(data);
-        self.count += data.len();
+        self.update_data(data);
+        #[cfg(feature = "hash-trace")]
+        self.trace.push(data.len());
+    }
+
+    /// Hashes the content of `cursor`. A read failure is returned instead of
+    /// panicking, since panics on CKB-VM abort without any useful exit code.
+    pub fn update_cursor(&mut self, cursor: Cursor) -> Result<(), Error> {
+        #[cfg(feature = "hash-trace")]
+        self.trace.push(cursor.size);
+        let mut buf = [0u8; BATCH_SIZE];
+        self.update_buffered(&mut buf, 0, cursor)
     }
 
-    pub fn update_cursor(&mut self, mut cursor: Cursor) {
+    /// Hashes the size of `cursor` as u32 little endian, then its content.
+    /// It's the same as `update` followed by `update_cursor`, but the size is
+    /// hashed together with the first chunk of the content, which saves a
+    /// blake2b update per length prefixed field.
+    pub fn update_cursor_with_len(&mut self, cursor: Cursor) -> Result<(), Error> {
+        #[cfg(feature = "hash-trace")]
+        {
+            self.trace.push(4);
+            self.trace.push(cursor.size);
+        }
         let mut buf = [0u8; BATCH_SIZE];
+        buf[..4].copy_from_slice(&(cursor.size as u32).to_le_bytes());
+        self.update_buffered(&mut buf, 4, cursor)
+    }
+
+    /// Hashes `buf[..filled]` followed by the content of `cursor`, using `buf`
+    /// to read the cursor in chunks.
+    fn update_buffered(
+        &mut self,
+        buf: &mut [u8; BATCH_SIZE],
+        mut filled: usize,
+        mut cursor: Cursor,
+    ) -> Result<(), Error> {
         while cursor.size > 0 {
-            let read_len = cursor.read_at(&mut buf).unwrap();
-            if read_len > 0 {
-                self.update(&buf[0..read_len]);
-                cursor = cursor.slice_by_start(read_len).unwrap();
+            let read_len = cursor.read_at(&mut buf[filled..])?;
+            if read_len == 0 {
+                return Err(LazyReaderError::OutOfBound(0, cursor.size).into());
             }
+            self.update_data(&buf[..filled + read_len]);
+            cursor = cursor.slice_by_start(read_len)?;
+            filled = 0;
         }
+        if filled > 0 {
+            self.update_data(&buf[..filled]);
+        }
+        Ok(())
+    }
+
+    fn update_data(&mut self, data: &[u8]) {
+        crate::hash_debug!("hash-debug bytes {}", crate::log::Hex(data));
+        self.blake2b.update(data);
+        self.count += data.len();
     }
 
     pub fn finalize(self, dst: &mut [u8]) {
         self.blake2b.finalize(dst)
     }
 
+    /// finalize the hash and return the total number of hashed bytes
+    pub fn finalize_with_count(self, dst: &mut [u8; 32]) -> usize {
+        let count = self.count;
+        self.blake2b.finalize(dst);
+        count
+    }
+
     pub fn count(&self) -> usize {
         self.count
     }
+
+    /// lengths of every `update` and `update_cursor` call, in order. Compare
+    /// the traces of on-chain and off-chain hashers to find the first field
+    /// where they differ.
+    #[cfg(feature = "hash-trace")]
+    pub fn trace(&self) -> &[usize] {
+        &self.trace
+    }
 }
@@ -0,0 +1,138 @@
+//! Builders of OTX witnesses for tests and tooling.
+//!
+//! They assemble the packed `OtxStart` and `Otx` witnesses and apply the same
+//! count rules as `cobuild_entry`, so malformed witnesses fail at build time
+//! instead of on-chain.
+use alloc::vec::Vec;
+use ckb_gen_types::prelude::*;
+use molecule::bytes::Bytes;
+
+use crate::{
+    error::Error,
+    schemas::{
+        basic::{self, Message, SealPair, SealPairVec},
+        blockchain::Byte,
+        top_level::{WitnessLayout, WitnessLayoutUnion},
+    },
+};
+
+/// Returns the `OtxStart` witness starting at the given input, output, cell
+/// dep and header dep indices.
+pub fn otx_start_witness(
+    start_input_cell: u32,
+    start_output_cell: u32,
+    start_cell_deps: u32,
+    start_header_deps: u32,
+) -> Bytes {
+    let otx_start = basic::OtxStart::new_builder()
+        .start_input_cell(start_input_cell.pack())
+        .start_output_cell(start_output_cell.pack())
+        .start_cell_deps(start_cell_deps.pack())
+        .start_header_deps(start_header_deps.pack())
+        .build();
+    WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::OtxStart(otx_start))
+        .build()
+        .as_bytes()
+}
+
+/// Accumulates the counts, message and seals of an `Otx` witness. Setting a
+/// dynamic count also sets the corresponding bit of the flag.
+#[derive(Default)]
+pub struct OtxBuilder {
+    flag: u8,
+    fixed: [u32; 4],
+    dynamic: [u32; 4],
+    message: Message,
+    seals: Vec<SealPair>,
+}
+
+impl OtxBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fixed_inputs(mut self, count: u32) -> Self {
+        self.fixed[0] = count;
+        self
+    }
+
+    pub fn fixed_outputs(mut self, count: u32) -> Self {
+        self.fixed[1] = count;
+        self
+    }
+
+    pub fn fixed_cell_deps(mut self, count: u32) -> Self {
+        self.fixed[2] = count;
+        self
+    }
+
+    pub fn fixed_header_deps(mut self, count: u32) -> Self {
+        self.fixed[3] = count;
+        self
+    }
+
+    pub fn dynamic_inputs(mut self, count: u32) -> Self {
+        self.dynamic[0] = count;
+        self.flag |= 0b0000_0001;
+        self
+    }
+
+    pub fn dynamic_outputs(mut self, count: u32) -> Self {
+        self.dynamic[1] = count;
+        self.flag |= 0b0000_0010;
+        self
+    }
+
+    pub fn dynamic_cell_deps(mut self, count: u32) -> Self {
+        self.dynamic[2] = count;
+        self.flag |= 0b0000_0100;
+        self
+    }
+
+    pub fn dynamic_header_deps(mut self, count: u32) -> Self {
+        self.dynamic[3] = count;
+        self.flag |= 0b0000_1000;
+        self
+    }
+
+    pub fn message(mut self, message: Message) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn seal(mut self, script_hash: [u8; 32], seal: Bytes) -> Self {
+        self.seals.push(
+            SealPair::new_builder()
+                .script_hash(script_hash.pack())
+                .seal(seal.pack())
+                .build(),
+        );
+        self
+    }
+
+    /// Returns the `Otx` witness, or `Error::WrongCount` if all fixed counts
+    /// are zero.
+    pub fn build(self) -> Result<Bytes, Error> {
+        if self.fixed.iter().all(|count| *count == 0) {
+            return Err(Error::WrongCount);
+        }
+        let otx = basic::Otx::new_builder()
+            .flag(Byte::new(self.flag))
+            .fixed_input_cells(self.fixed[0].pack())
+            .fixed_output_cells(self.fixed[1].pack())
+            .fixed_cell_deps(self.fixed[2].pack())
+            .fixed_header_deps(self.fixed[3].pack())
+            .dynamic_input_cells(self.dynamic[0].pack())
+            .dynamic_output_cells(self.dynamic[1].pack())
+            .dynamic_cell_deps(self.dynamic[2].pack())
+            .dynamic_header_deps(self.dynamic[3].pack())
+            .message(self.message)
+            .seals(SealPairVec::new_builder().set(self.seals).build())
+            .build();
+        Ok(WitnessLayout::new_builder()
+            .set(WitnessLayoutUnion::Otx(otx))
+            .build()
+            .as_bytes())
+    }
+}
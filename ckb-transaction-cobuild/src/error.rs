@@ -1,4 +1,7 @@
-use ckb_std::error::SysError;
+use alloc::boxed::Box;
+use core::{array::TryFromSliceError, fmt};
+
+use ckb_std::{ckb_constants::Source, error::SysError};
 use molecule::error::VerificationError;
 pub use molecule::lazy_reader::Error as LazyReaderError;
 
@@ -7,16 +10,161 @@ pub enum Error {
     Sys(SysError),
     LazyReader(LazyReaderError),
     MoleculeEncoding,
+    /// A molecule or lazy reader failure while reading `field`. `cause` is
+    /// the original `MoleculeEncoding` or `LazyReader` error.
+    MoleculeEncodingAt {
+        field: &'static str,
+        cause: Box<Error>,
+    },
     WrongSighashAll,
     WrongWitnessLayout,
     WrongOtxStart,
     WrongScriptType,
     WrongOtx,
     NoSealFound,
+    /// The OTX containing the current script carries no seal at all.
+    NoSeals,
+    /// A molecule verification failure of a packed type, with its kind: e.g.
+    /// a total size or header mismatch, or broken offsets.
+    Verification(VerificationError),
+    /// A slice converted to an array has a different length, e.g.
+    /// `seal[..64].try_into()?` with a shorter seal.
+    SliceLength,
     AuthError,
     ScriptHashAbsent,
     WrongCount,
     InvalidOtxFlag,
+    DuplicateOtxStart,
+    OtxBeforeStart,
+    OtxNotContiguous,
+    DataLength,
+    /// A molecule or lazy reader failure while reading the cell at `index`
+    /// of `source`, e.g. an input cell hashed into a signing message hash.
+    /// `cause` is the original error, like in `MoleculeEncodingAt`.
+    CellReadFailed {
+        index: u32,
+        source: Source,
+        cause: Box<Error>,
+    },
+    /// The OTX group asked for, e.g. by `otx::verify_otx_group`, isn't in the
+    /// transaction.
+    OtxGroupNotFound,
+    /// The message has no action at the index asked for, see
+    /// `Message::action_at`.
+    ActionNotFound,
+}
+
+impl Error {
+    /// Returns the exit code of this error, which can be returned from the
+    /// script directly. The numbering is stable across releases:
+    ///
+    /// | code | error |
+    /// |------|-------|
+    /// | 1 | `Sys(SysError::IndexOutOfBound)` |
+    /// | 2 | `Sys(SysError::ItemMissing)` |
+    /// | 3 | `Sys(SysError::LengthNotEnough(_))` |
+    /// | 4 | `Sys(SysError::Encoding)` |
+    /// | 5 | `AuthError` |
+    /// | 6 | `WrongSighashAll` |
+    /// | 7 | `WrongWitnessLayout` |
+    /// | 8 | `WrongOtxStart` |
+    /// | 9 | `InvalidOtxFlag` |
+    /// | 10 | `WrongCount` |
+    /// | 11 | `MoleculeEncoding` |
+    /// | 12 | `LazyReader(_)` |
+    /// | 13 | `WrongScriptType` |
+    /// | 14 | `WrongOtx` |
+    /// | 15 | `NoSealFound` |
+    /// | 16 | `ScriptHashAbsent` |
+    /// | 17 | `Sys(SysError::Unknown(_))` |
+    /// | 18 | `DuplicateOtxStart` |
+    /// | 19 | `OtxBeforeStart` |
+    /// | 20 | `OtxNotContiguous` |
+    /// | 21 | `DataLength` |
+    /// | 22 | `MoleculeEncodingAt { .. }` |
+    /// | 23 | `CellReadFailed { .. }` |
+    /// | 24 | `NoSeals` |
+    /// | 25 | `Verification(_)` |
+    /// | 26 | `SliceLength` |
+    /// | 27 | `OtxGroupNotFound` |
+    /// | 28 | `ActionNotFound` |
+    ///
+    /// New variants get new codes; existing codes are never reused.
+    pub fn code(&self) -> i8 {
+        match self {
+            Error::Sys(SysError::IndexOutOfBound) => 1,
+            Error::Sys(SysError::ItemMissing) => 2,
+            Error::Sys(SysError::LengthNotEnough(_)) => 3,
+            Error::Sys(SysError::Encoding) => 4,
+            Error::AuthError => 5,
+            Error::WrongSighashAll => 6,
+            Error::WrongWitnessLayout => 7,
+            Error::WrongOtxStart => 8,
+            Error::InvalidOtxFlag => 9,
+            Error::WrongCount => 10,
+            Error::MoleculeEncoding => 11,
+            Error::LazyReader(_) => 12,
+            Error::WrongScriptType => 13,
+            Error::WrongOtx => 14,
+            Error::NoSealFound => 15,
+            Error::ScriptHashAbsent => 16,
+            Error::Sys(SysError::Unknown(_)) => 17,
+            Error::DuplicateOtxStart => 18,
+            Error::OtxBeforeStart => 19,
+            Error::OtxNotContiguous => 20,
+            Error::DataLength => 21,
+            Error::MoleculeEncodingAt { .. } => 22,
+            Error::CellReadFailed { .. } => 23,
+            Error::NoSeals => 24,
+            Error::Verification(_) => 25,
+            Error::SliceLength => 26,
+            Error::OtxGroupNotFound => 27,
+            Error::ActionNotFound => 28,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sys(e) => write!(f, "syscall error: {:?}", e),
+            Error::LazyReader(e) => write!(f, "lazy reader error: {:?}", e),
+            Error::MoleculeEncoding => write!(f, "molecule encoding error"),
+            Error::MoleculeEncodingAt { field, cause } => {
+                write!(f, "molecule encoding error at {}: {}", field, cause)
+            }
+            Error::WrongSighashAll => write!(f, "wrong SighashAll witness"),
+            Error::WrongWitnessLayout => write!(f, "wrong witness layout"),
+            Error::WrongOtxStart => write!(f, "wrong OtxStart witness"),
+            Error::WrongScriptType => write!(f, "wrong script type in action"),
+            Error::WrongOtx => write!(f, "wrong Otx witness"),
+            Error::NoSealFound => write!(f, "no seal found for current script hash"),
+            Error::NoSeals => write!(f, "no seals in OTX"),
+            Error::Verification(e) => write!(f, "molecule verification error: {:?}", e),
+            Error::AuthError => write!(f, "authentication failed"),
+            Error::ScriptHashAbsent => write!(f, "action script hash absent in transaction"),
+            Error::WrongCount => write!(f, "wrong cell or dep count"),
+            Error::InvalidOtxFlag => write!(f, "invalid OTX flag"),
+            Error::DuplicateOtxStart => write!(f, "OtxStart followed by another OtxStart"),
+            Error::OtxBeforeStart => write!(f, "Otx witness without a preceding OtxStart"),
+            Error::OtxNotContiguous => write!(f, "Otx witnesses are not contiguous"),
+            Error::DataLength => write!(f, "unexpected data length"),
+            Error::SliceLength => write!(f, "slice length doesn't match the array length"),
+            Error::OtxGroupNotFound => write!(f, "OTX group not found"),
+            Error::ActionNotFound => write!(f, "action not found in message"),
+            Error::CellReadFailed {
+                index,
+                source,
+                cause,
+            } => {
+                write!(
+                    f,
+                    "failed to read cell {} of {:?}: {}",
+                    index, source, cause
+                )
+            }
+        }
+    }
 }
 
 impl From<SysError> for Error {
@@ -26,8 +174,14 @@ impl From<SysError> for Error {
 }
 
 impl From<VerificationError> for Error {
-    fn from(_: VerificationError) -> Self {
-        Error::MoleculeEncoding
+    fn from(e: VerificationError) -> Self {
+        Error::Verification(e)
+    }
+}
+
+impl From<TryFromSliceError> for Error {
+    fn from(_: TryFromSliceError) -> Self {
+        Error::SliceLength
     }
 }
 
@@ -36,3 +190,38 @@ impl From<LazyReaderError> for Error {
         Error::LazyReader(e)
     }
 }
+
+/// Labels the molecule and lazy reader failures of a parse site with the
+/// field being read, as `Error::MoleculeEncodingAt` carrying the original
+/// error. Other errors, e.g. syscall errors, are kept as is, and so is
+/// `Error::Verification`, whose kind already tells what's wrong.
+pub(crate) trait ResultExt<T> {
+    fn at(self, field: &'static str) -> Result<T, Error>;
+
+    /// Same as `at`, for the cell at `index` of `source`, as
+    /// `Error::CellReadFailed`.
+    fn at_cell(self, index: usize, source: Source) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn at(self, field: &'static str) -> Result<T, Error> {
+        self.map_err(|e| match e.into() {
+            e @ (Error::MoleculeEncoding | Error::LazyReader(_)) => Error::MoleculeEncodingAt {
+                field,
+                cause: Box::new(e),
+            },
+            e => e,
+        })
+    }
+
+    fn at_cell(self, index: usize, source: Source) -> Result<T, Error> {
+        self.map_err(|e| match e.into() {
+            e @ (Error::MoleculeEncoding | Error::LazyReader(_)) => Error::CellReadFailed {
+                index: index as u32,
+                source,
+                cause: Box::new(e),
+            },
+            e => e,
+        })
+    }
+}
@@ -0,0 +1,175 @@
+//! Off-chain computation of signing message hashes.
+//!
+//! The functions here work on packed molecule types instead of syscalls, so
+//! they can run in wallets, backends and integration tests. They produce the
+//! exact same hashes as the on-chain `generate_otx_smh` and
+//! `generate_signing_message_hash`.
+use ckb_hash::blake2b_256;
+use molecule::prelude::Entity;
+
+use crate::{
+    blake2b::{new_otx_blake2b, new_sighash_all_blake2b, new_sighash_all_only_blake2b},
+    error::Error,
+    otx::{index_range, OtxSigningRange},
+    schemas::{basic, blockchain},
+};
+
+/// Calculates the OTX signing message hash for `signing_range`. The input
+/// cells referenced by `raw_tx` are resolved by `resolved_inputs`, which must
+/// be indexed the same way as the inputs of `raw_tx`.
+pub fn otx_signing_message_hash(
+    message: &basic::Message,
+    raw_tx: &blockchain::RawTransaction,
+    resolved_inputs: &basic::ResolvedInputs,
+    signing_range: &OtxSigningRange,
+) -> Result<[u8; 32], Error> {
+    otx_signing_message_hash_with_cell_dep_data(
+        message,
+        raw_tx,
+        resolved_inputs,
+        &blockchain::BytesVec::default(),
+        signing_range,
+    )
+}
+
+/// Same as `otx_signing_message_hash`, with the data of the cell deps, indexed
+/// the same way as the cell deps of `raw_tx`. It's required when
+/// `signing_range.hash_cell_dep_data` is set, otherwise `Error::WrongCount` is
+/// returned.
+pub fn otx_signing_message_hash_with_cell_dep_data(
+    message: &basic::Message,
+    raw_tx: &blockchain::RawTransaction,
+    resolved_inputs: &basic::ResolvedInputs,
+    cell_deps_data: &blockchain::BytesVec,
+    signing_range: &OtxSigningRange,
+) -> Result<[u8; 32], Error> {
+    let mut hasher = new_otx_blake2b();
+    hasher.update(message.as_slice());
+
+    hasher.update(&signing_range.inputs_count.to_le_bytes());
+    for index in index_range(signing_range.input_start, signing_range.inputs_count)? {
+        let input = raw_tx.inputs().get(index).ok_or(Error::WrongCount)?;
+        let input_cell = resolved_inputs
+            .outputs()
+            .get(index)
+            .ok_or(Error::WrongCount)?;
+        let input_cell_data = resolved_inputs
+            .outputs_data()
+            .get(index)
+            .ok_or(Error::WrongCount)?
+            .raw_data();
+        // input
+        hasher.update(input.as_slice());
+        // input cell
+        hasher.update(input_cell.as_slice());
+        // input cell data size
+        hasher.update(&(input_cell_data.len() as u32).to_le_bytes());
+        // input cell data
+        hasher.update(&input_cell_data);
+    }
+
+    hasher.update(&signing_range.outputs_count.to_le_bytes());
+    for index in index_range(signing_range.output_start, signing_range.outputs_count)? {
+        let output = raw_tx.outputs().get(index).ok_or(Error::WrongCount)?;
+        let data = raw_tx
+            .outputs_data()
+            .get(index)
+            .ok_or(Error::WrongCount)?
+            .raw_data();
+        if signing_range.output_hash_mask.exclude_capacity {
+            // output cell without capacity
+            hasher.update(output.lock().as_slice());
+            match output.type_().to_opt() {
+                Some(type_script) => {
+                    hasher.update(&[1]);
+                    hasher.update(type_script.as_slice());
+                }
+                None => hasher.update(&[0]),
+            }
+        } else {
+            // output cell
+            hasher.update(output.as_slice());
+        }
+        // output cell data size
+        hasher.update(&(data.len() as u32).to_le_bytes());
+        // output cell data
+        hasher.update(&data);
+    }
+
+    hasher.update(&signing_range.cell_deps_count.to_le_bytes());
+    for index in index_range(signing_range.cell_dep_start, signing_range.cell_deps_count)? {
+        let cell_dep = raw_tx.cell_deps().get(index).ok_or(Error::WrongCount)?;
+        hasher.update(cell_dep.as_slice());
+        if signing_range.hash_cell_dep_data {
+            let data = cell_deps_data
+                .get(index)
+                .ok_or(Error::WrongCount)?
+                .raw_data();
+            // cell dep data size
+            hasher.update(&(data.len() as u32).to_le_bytes());
+            // cell dep data
+            hasher.update(&data);
+        }
+    }
+
+    hasher.update(&signing_range.header_deps_count.to_le_bytes());
+    for index in index_range(
+        signing_range.header_dep_start,
+        signing_range.header_deps_count,
+    )? {
+        let header_dep = raw_tx.header_deps().get(index).ok_or(Error::WrongCount)?;
+        hasher.update(header_dep.as_slice());
+    }
+
+    let mut result = [0u8; 32];
+    hasher.finalize(&mut result);
+    Ok(result)
+}
+
+/// Calculates the signing message hash for SighashAll, or SighashAllOnly when
+/// `message` is `None`. The input cells of `tx` are resolved by
+/// `resolved_inputs`. The witnesses of `tx` below the inputs count are not
+/// hashed, see `sighashall::generate_signing_message_hash`.
+pub fn signing_message_hash(
+    message: &Option<basic::Message>,
+    tx: &blockchain::Transaction,
+    resolved_inputs: &basic::ResolvedInputs,
+) -> Result<[u8; 32], Error> {
+    // message
+    let mut hasher = match message {
+        Some(m) => {
+            let mut hasher = new_sighash_all_blake2b();
+            hasher.update(m.as_slice());
+            hasher
+        }
+        None => new_sighash_all_only_blake2b(),
+    };
+    // tx hash
+    hasher.update(&blake2b_256(tx.raw().as_slice()));
+    // inputs cell and data
+    let inputs_len = tx.raw().inputs().len();
+    for index in 0..inputs_len {
+        let input_cell = resolved_inputs
+            .outputs()
+            .get(index)
+            .ok_or(Error::WrongCount)?;
+        let input_cell_data = resolved_inputs
+            .outputs_data()
+            .get(index)
+            .ok_or(Error::WrongCount)?
+            .raw_data();
+        hasher.update(input_cell.as_slice());
+        hasher.update(&(input_cell_data.len() as u32).to_le_bytes());
+        hasher.update(&input_cell_data);
+    }
+    // extra witnesses
+    for witness in tx.witnesses().into_iter().skip(inputs_len) {
+        let witness = witness.raw_data();
+        hasher.update(&(witness.len() as u32).to_le_bytes());
+        hasher.update(&witness);
+    }
+
+    let mut result = [0u8; 32];
+    hasher.finalize(&mut result);
+    Ok(result)
+}
@@ -1,10 +1,12 @@
 use core::cmp::min;
 
-use super::schemas2::blockchain;
-use alloc::boxed::Box;
-use ckb_std::{ckb_constants::Source, error::SysError, syscalls};
+use super::schemas2::{basic, blockchain};
+use crate::syscalls;
+use alloc::{boxed::Box, collections::btree_map::BTreeMap, rc::Rc, vec::Vec};
+use ckb_std::{ckb_constants::Source, error::SysError};
+use core::cell::{Cell, RefCell};
 
-pub use molecule::lazy_reader::{Cursor, Error, Read};
+pub use molecule::lazy_reader::{Cursor, Error, Read, MAX_CACHE_SIZE};
 
 fn read_data<F: Fn(&mut [u8], usize) -> Result<usize, SysError>>(
     load_func: F,
@@ -67,13 +69,54 @@ impl From<TransactionReader> for Cursor {
     }
 }
 
+/// Returns the current transaction, read lazily via syscalls.
+///
+/// Every call sets up a new reader, which loads the transaction size with a
+/// syscall and allocates its own read buffer. Use `shared_transaction` instead
+/// to reuse the reader set up by a previous call within the script execution.
 pub fn new_transaction() -> blockchain::Transaction {
     let tx_reader = TransactionReader::default();
     let cursor: Cursor = tx_reader.into();
     blockchain::Transaction::from(cursor)
 }
 
+pub(crate) type SharedTransactionSlot = RefCell<Option<Rc<blockchain::Transaction>>>;
+
+#[cfg(not(feature = "mock-syscalls"))]
+fn with_shared_transaction_slot<T, F: FnOnce(&SharedTransactionSlot) -> T>(f: F) -> T {
+    struct Slot(SharedTransactionSlot);
+    // A script runs single threaded on CKB-VM.
+    unsafe impl Sync for Slot {}
+    static SHARED_TRANSACTION: Slot = Slot(RefCell::new(None));
+    f(&SHARED_TRANSACTION.0)
+}
+
+#[cfg(feature = "mock-syscalls")]
+use crate::mock::with_shared_transaction_slot;
+
+/// Returns the current transaction, like `new_transaction`, but its reader is
+/// set up by the first call only: later calls within the same script execution
+/// return the same transaction, with the parts already read still in its read
+/// buffer. The transaction can't change during a script execution, so it stays
+/// valid until the script exits.
+///
+/// The entry points of this crate, e.g. `cobuild_entry`, `fetch_message` and
+/// `generate_signing_message_hash`, all read the transaction through it. With
+/// the `mock-syscalls` feature, the transaction is shared per thread until
+/// `set_mock_transaction` is called again.
+pub fn shared_transaction() -> Rc<blockchain::Transaction> {
+    with_shared_transaction_slot(|slot| {
+        slot.borrow_mut()
+            .get_or_insert_with(|| Rc::new(new_transaction()))
+            .clone()
+    })
+}
+
 // Input cell are not in current transaction. Can't use `TransactionReader`
+//
+// Despite the name, it reads a cell from any source accepted by `load_cell`:
+// `Source::Input`, `Source::GroupInput`, `Source::Output`,
+// `Source::GroupOutput` and `Source::CellDep`. `index` is relative to `source`.
 pub struct InputCellReader {
     pub total_size: usize,
     pub index: usize,
@@ -108,6 +151,8 @@ impl From<InputCellReader> for Cursor {
     }
 }
 
+/// Returns the cell at `index` of `source`, which can be any of the sources
+/// listed on `InputCellReader`, e.g. `Source::GroupInput`.
 pub fn new_input_cell(index: usize, source: Source) -> Result<blockchain::CellOutput, Error> {
     let reader = InputCellReader::try_new(index, source)?;
     let cursor: Cursor = reader.into();
@@ -115,6 +160,9 @@ pub fn new_input_cell(index: usize, source: Source) -> Result<blockchain::CellOu
 }
 
 // Input cell data are not in current transaction. Can't use `TransactionReader`
+//
+// Like `InputCellReader`, it reads the cell data from any source accepted by
+// `load_cell_data`, with `index` relative to `source`.
 pub struct InputCellDataReader {
     pub total_size: usize,
     pub index: usize,
@@ -149,6 +197,8 @@ impl From<InputCellDataReader> for Cursor {
     }
 }
 
+/// Returns the data of the cell at `index` of `source`, which can be any of
+/// the sources listed on `InputCellReader`, e.g. `Source::GroupInput`.
 pub fn new_input_cell_data(index: usize, source: Source) -> Result<Cursor, Error> {
     let reader = InputCellDataReader::try_new(index, source)?;
     let cursor: Cursor = reader.into();
@@ -194,3 +244,181 @@ pub fn new_witness(index: usize, source: Source) -> Result<Cursor, Error> {
     let cursor: Cursor = reader.into();
     Ok(cursor)
 }
+
+// Reads from a buffer which has been loaded in memory
+struct BufferReader {
+    data: Rc<Vec<u8>>,
+}
+
+impl Read for BufferReader {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, Error> {
+        if offset >= self.data.len() {
+            return Err(Error::OutOfBound(offset, self.data.len()));
+        }
+        let read_len = min(buf.len(), self.data.len() - offset);
+        buf[..read_len].copy_from_slice(&self.data[offset..offset + read_len]);
+        Ok(read_len)
+    }
+}
+
+impl From<BufferReader> for Cursor {
+    fn from(data: BufferReader) -> Self {
+        Cursor::new(data.data.len(), Box::new(data))
+    }
+}
+
+/// Returns a cursor over `data` in memory, e.g. to build a mocked transaction.
+pub fn new_cursor_from_vec(data: Vec<u8>) -> Cursor {
+    BufferReader {
+        data: Rc::new(data),
+    }
+    .into()
+}
+
+/// Default number of bytes of read buffers held by the cursors a
+/// `CachedTransaction` memoizes, 32 cursors. That's 16 inputs, whose cells and
+/// data are read by both the OTX and the SighashAll signing message hashes,
+/// while keeping the memoized buffers to an eighth of the 516 KiB heap of
+/// `ckb_std::default_alloc!()`.
+pub const DEFAULT_CELL_CACHE_LIMIT: usize = 64 * 1024;
+
+type CellCache = RefCell<BTreeMap<(usize, u64), Cursor>>;
+
+///
+/// A transaction which memoizes the cursors over the input cells and input
+/// cell data it has loaded. The SighashAll and OTX signing message hashes both
+/// read input cells, so when both run in the same script, the cursor of every
+/// cell is only set up once. Its clones share the read buffer, which holds the
+/// last 2 KiB read, so cells up to that size are also loaded only once.
+///
+/// Every memoized cursor holds a read buffer of `MAX_CACHE_SIZE` bytes, and at
+/// most `cache_limit` bytes of them are memoized: once another buffer wouldn't
+/// fit, cursors are set up again on every access, like `InputCellReader` does.
+/// `without_cache` returns a transaction which never memoizes.
+///
+pub struct CachedTransaction {
+    tx: blockchain::Transaction,
+    cache_limit: usize,
+    cached_bytes: Cell<usize>,
+    input_cells: CellCache,
+    input_cell_data: CellCache,
+}
+
+impl Default for CachedTransaction {
+    fn default() -> Self {
+        Self::from_transaction(
+            shared_transaction().as_ref().clone(),
+            DEFAULT_CELL_CACHE_LIMIT,
+        )
+    }
+}
+
+impl CachedTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn without_cache() -> Self {
+        Self::from_transaction(shared_transaction().as_ref().clone(), 0)
+    }
+
+    /// Wraps `tx`, which must be the current transaction, e.g. a clone of the
+    /// one returned by `shared_transaction`, so its reader is shared. At most
+    /// `cache_limit` bytes of read buffers are memoized, `0` disables the
+    /// cache as `without_cache` does.
+    pub fn from_transaction(tx: blockchain::Transaction, cache_limit: usize) -> Self {
+        Self {
+            tx,
+            cache_limit,
+            cached_bytes: Cell::new(0),
+            input_cells: RefCell::new(BTreeMap::new()),
+            input_cell_data: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn transaction(&self) -> &blockchain::Transaction {
+        &self.tx
+    }
+
+    /// Number of bytes of read buffers memoized so far, at most the
+    /// `cache_limit`.
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes.get()
+    }
+
+    /// Same as `InputCellReader`, the cursor is set up on first access.
+    pub fn input_cell(&self, index: usize, source: Source) -> Result<Cursor, Error> {
+        self.get_or_load(&self.input_cells, index, source, || {
+            Ok(InputCellReader::try_new(index, source)?.into())
+        })
+    }
+
+    /// Same as `new_input_cell_data`, the cursor is set up on first access.
+    pub fn input_cell_data(&self, index: usize, source: Source) -> Result<Cursor, Error> {
+        self.get_or_load(&self.input_cell_data, index, source, || {
+            new_input_cell_data(index, source)
+        })
+    }
+
+    /// The cursor returned by `load` is only memoized if its read buffer fits
+    /// in the remaining cache.
+    fn get_or_load<F: FnOnce() -> Result<Cursor, Error>>(
+        &self,
+        cache: &CellCache,
+        index: usize,
+        source: Source,
+        load: F,
+    ) -> Result<Cursor, Error> {
+        let key = (index, source as u64);
+        if let Some(cursor) = cache.borrow().get(&key) {
+            return Ok(cursor.clone());
+        }
+        let cursor = load()?;
+        let cached_bytes = self.cached_bytes.get() + MAX_CACHE_SIZE;
+        if cached_bytes <= self.cache_limit {
+            self.cached_bytes.set(cached_bytes);
+            cache.borrow_mut().insert(key, cursor.clone());
+        }
+        Ok(cursor)
+    }
+}
+
+/// Provides the resolved input cells and their data, which are not part of
+/// the transaction itself.
+pub trait ResolvedCells {
+    fn resolved_cell(&self, index: usize) -> Result<Cursor, Error>;
+    fn resolved_cell_data(&self, index: usize) -> Result<Cursor, Error>;
+
+    /// Returns the data of the cell dep at `index`. It's only needed for OTX
+    /// signing ranges with `hash_cell_dep_data`, so it isn't provided by
+    /// default.
+    fn resolved_cell_dep_data(&self, index: usize) -> Result<Cursor, Error> {
+        Err(Error::OutOfBound(index, 0))
+    }
+}
+
+/// Loads the input cells with syscalls.
+impl ResolvedCells for CachedTransaction {
+    fn resolved_cell(&self, index: usize) -> Result<Cursor, Error> {
+        self.input_cell(index, Source::Input)
+    }
+
+    fn resolved_cell_data(&self, index: usize) -> Result<Cursor, Error> {
+        self.input_cell_data(index, Source::Input)
+    }
+
+    fn resolved_cell_dep_data(&self, index: usize) -> Result<Cursor, Error> {
+        new_input_cell_data(index, Source::CellDep)
+    }
+}
+
+/// Reads the input cells from a `ResolvedInputs`, without syscalls.
+impl ResolvedCells for basic::ResolvedInputs {
+    fn resolved_cell(&self, index: usize) -> Result<Cursor, Error> {
+        Ok(self.outputs()?.get(index)?.cursor)
+    }
+
+    fn resolved_cell_data(&self, index: usize) -> Result<Cursor, Error> {
+        self.outputs_data()?.get(index)
+    }
+}
@@ -0,0 +1,75 @@
+use alloc::vec;
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    error::SysError,
+};
+
+use crate::{
+    blake2b::new_blake2b_with_personal,
+    error::Error,
+    lazy_reader::{new_witness, shared_transaction},
+    log,
+    syscalls::{self, load_tx_hash, load_witness_args},
+};
+
+/// personalization of the default CKB hash, used by secp256k1_blake160
+const PERSONALIZATION_CKB_DEFAULT: &[u8] = b"ckb-default-hash";
+
+///
+/// Generate the classic WitnessArgs based signing message hash, as used by
+/// secp256k1_blake160_sighash_all. A lock script can fall back to it when
+/// `cobuild_entry` returns `false`.
+///
+/// The hash covers, in order: the transaction hash, the first witness of the
+/// script group with its `lock` field filled by zeros, the other witnesses in
+/// the script group and the witnesses beyond the inputs count. Every witness is
+/// prefixed with its size as u64 little endian.
+///
+pub fn legacy_signing_message_hash() -> Result<[u8; 32], Error> {
+    let tx = shared_transaction();
+    let mut hasher = new_blake2b_with_personal(PERSONALIZATION_CKB_DEFAULT);
+    // tx hash
+    hasher.update(&load_tx_hash()?);
+
+    // first witness in script group, with zero-filled lock
+    let witness_args = load_witness_args(0, Source::GroupInput)?;
+    let zero_lock: Option<Bytes> = witness_args
+        .lock()
+        .to_opt()
+        .map(|lock| vec![0u8; lock.raw_data().len()].into());
+    let witness_for_digest = witness_args.as_builder().lock(zero_lock.pack()).build();
+    hasher.update(&(witness_for_digest.as_slice().len() as u64).to_le_bytes());
+    hasher.update(witness_for_digest.as_slice());
+
+    // other witnesses in script group
+    let mut index = 1;
+    let mut buf = [0u8; 4];
+    loop {
+        match syscalls::load_witness(&mut buf, 0, index, Source::GroupInput) {
+            Ok(_) | Err(SysError::LengthNotEnough(_)) => {}
+            Err(SysError::IndexOutOfBound) => break,
+            Err(err) => return Err(err.into()),
+        }
+        let witness = new_witness(index, Source::GroupInput)?;
+        hasher.update(&(witness.size as u64).to_le_bytes());
+        hasher.update_cursor(witness)?;
+        index += 1;
+    }
+
+    // witnesses beyond the inputs count
+    let inputs_len = tx.raw()?.inputs()?.len()?;
+    for witness in tx.witnesses()?.iter().skip(inputs_len) {
+        hasher.update(&(witness.size as u64).to_le_bytes());
+        hasher.update_cursor(witness)?;
+    }
+
+    let mut result = [0u8; 32];
+    let count = hasher.finalize_with_count(&mut result);
+    log!(
+        "legacy_signing_message_hash totally hashed {} bytes and hash is {:?}",
+        count,
+        result
+    );
+    Ok(result)
+}
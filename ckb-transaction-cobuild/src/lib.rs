@@ -7,7 +7,7 @@
 //! ```
 //! impl Callback for Verifier {
 //!     fn invoke(
-//!         &self,
+//!         &mut self,
 //!         seal: &[u8],
 //!         signing_message_hash: &[u8; 32],
 //!     ) -> Result<(), ckb_transaction_cobuild::error::Error> {
@@ -23,43 +23,70 @@
 //!
 //! To support cobuild, proceed with calling `cobuild_entry`:
 //! ```rust
-//! let verifier = Verifier::new();
-//! let cobuild_activated = cobuild_entry(&verifier)?;
+//! let mut verifier = Verifier::new();
+//! let cobuild_activated = cobuild_entry(&mut verifier)?;
 //! ```
 //! The boolean `cobuild_activated` denotes whether cobuild mode has been
 //! enabled. If not, the script may default to executing its legacy code.
 //!
+//! Use `cobuild_entry_detailed` instead to get a `CobuildResult`, which also
+//! tells how many times the verifier ran and which paths were taken.
+//!
 //! ### For Type Script
 //! To retrieve messages, use the `fetch_message` function. For comprehensive
 //! details on utilizing messages and actions within the cobuild framework,
 //! refer to the cobuild specification.
 //!
+//! ### Off-chain
+//! With the `std` feature enabled, the `host` module computes the same signing
+//! message hashes from packed molecule types, without syscalls.
+//!
 
 #![no_std]
 extern crate alloc;
 
+#[cfg(feature = "secp256k1")]
+pub mod auth;
 pub mod blake2b;
+#[cfg(feature = "builder")]
+pub mod builder;
 pub mod error;
+#[cfg(feature = "std")]
+pub mod host;
 pub mod lazy_reader;
 pub mod legacy;
 pub mod log;
+#[cfg(feature = "mock-syscalls")]
+pub mod mock;
 pub mod otx;
+pub mod owned;
 pub mod schemas;
 pub mod schemas2;
+pub mod seal;
 pub mod sighashall;
+mod syscalls;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub mod utils;
 
-use alloc::vec::Vec;
-use ckb_std::{
-    ckb_constants::Source,
-    high_level::{load_cell_lock_hash, load_script_hash},
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use ckb_std::{ckb_constants::Source, error::SysError, high_level::QueryIter};
+use core::fmt;
+use error::{Error, ResultExt};
+use lazy_reader::{new_witness, shared_transaction, CachedTransaction, DEFAULT_CELL_CACHE_LIMIT};
+use otx::{
+    count_seals, fetch_otx_start, find_seal, generate_otx_smh_cached, validate_otx_totals,
+    OtxContext, OtxDynamicConfigs, OtxSigningRange,
+};
+use schemas2::{basic, blockchain, top_level};
+use sighashall::{
+    cobuild_normal_entry_cached, generate_signing_message_hash_cached, load_witness_len, message_of,
+};
+use syscalls::{load_cell_lock_hash, load_script, load_script_hash};
+use utils::{
+    cache_by_code_hash, cache_script_hashes_with_cell_deps, check_message, code_hash_key, ct_eq_32,
+    is_script_included, AnyWitnessLayout, ScriptLocation, ScriptType, WitnessLayoutExt,
 };
-use error::Error;
-use lazy_reader::new_transaction;
-use otx::{fetch_otx_start, generate_otx_smh, OtxDynamicConfigs, OtxSigningRange};
-use schemas2::{blockchain, top_level};
-use sighashall::cobuild_normal_entry;
-use utils::{cache_script_hashes, check_message, is_script_included, ScriptType};
 
 ///
 /// This is the callback trait should be implemented in lock script by
@@ -67,14 +94,166 @@ use utils::{cache_script_hashes, check_message, is_script_included, ScriptType};
 ///
 /// - **`seal`**: Typically represents a signature.
 /// - **`signing_message_hash`**: The hashed message that the owner signed.
+///
+/// The same verifier may be invoked several times in one execution: once per
+/// matched OTX group and once more for the normal entry. It takes `&mut self`
+/// so implementors can accumulate state across these calls.
 pub trait Callback {
-    fn invoke(&self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error>;
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error>;
+
+    /// Invoked for OTX groups instead of `invoke`, with the context of the
+    /// group being verified. Forwards to `invoke` by default.
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        _ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        self.invoke(seal, signing_message_hash)
+    }
+}
+
+impl<T: Callback + ?Sized> Callback for &mut T {
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        (**self).invoke(seal, signing_message_hash)
+    }
+
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        (**self).invoke_otx(seal, signing_message_hash, ctx)
+    }
+}
+
+///
+/// Like `Callback`, but the verifier also receives the message being
+/// authorized, so a lock script can bind its signature to the actions.
+///
+/// - **`message`**: The `Message` of the OTX group, or of the `SighashAll`
+///   witness in the normal entry. It is `None` for `SighashAllOnly`.
+pub trait MessageCallback {
+    fn invoke(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        message: Option<&basic::Message>,
+    ) -> Result<(), Error>;
+
+    /// Invoked for OTX groups instead of `invoke`, with the context of the
+    /// group being verified. Forwards to `invoke` by default.
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        message: Option<&basic::Message>,
+        _ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        self.invoke(seal, signing_message_hash, message)
+    }
+}
+
+impl<T: MessageCallback + ?Sized> MessageCallback for &mut T {
+    fn invoke(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        message: Option<&basic::Message>,
+    ) -> Result<(), Error> {
+        (**self).invoke(seal, signing_message_hash, message)
+    }
+
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        message: Option<&basic::Message>,
+        ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        (**self).invoke_otx(seal, signing_message_hash, message, ctx)
+    }
+}
+
+/// Logs every signing message hash passed to `verifier` more than once, with
+/// the `paranoid` feature. OTX and normal hashes use distinct
+/// personalizations, so they should never collide.
+#[cfg(feature = "paranoid")]
+struct CollisionCheck<F> {
+    verifier: F,
+    hashes: Vec<[u8; 32]>,
+}
+
+#[cfg(feature = "paranoid")]
+impl<F> CollisionCheck<F> {
+    fn check(&mut self, signing_message_hash: &[u8; 32]) {
+        if self.hashes.contains(signing_message_hash) {
+            log!(
+                "signing message hash {} is computed twice",
+                log::Hex(signing_message_hash)
+            );
+        }
+        self.hashes.push(*signing_message_hash);
+    }
+}
+
+#[cfg(feature = "paranoid")]
+impl<F: MessageCallback> MessageCallback for CollisionCheck<F> {
+    fn invoke(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        message: Option<&basic::Message>,
+    ) -> Result<(), Error> {
+        self.check(signing_message_hash);
+        self.verifier.invoke(seal, signing_message_hash, message)
+    }
+
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        message: Option<&basic::Message>,
+        ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        self.check(signing_message_hash);
+        self.verifier
+            .invoke_otx(seal, signing_message_hash, message, ctx)
+    }
 }
 
-#[derive(Debug)]
+/// Adapts a `Callback` to `MessageCallback` by ignoring the message.
+pub(crate) struct IgnoreMessage<F>(pub F);
+
+impl<F: Callback> MessageCallback for IgnoreMessage<F> {
+    fn invoke(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        _message: Option<&basic::Message>,
+    ) -> Result<(), Error> {
+        self.0.invoke(seal, signing_message_hash)
+    }
+
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        _message: Option<&basic::Message>,
+        ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        self.0.invoke_otx(seal, signing_message_hash, ctx)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct CobuildState {
     pub otx_start_index: usize,
 
+    /// `OtxStart.start_input_cell`. It's never changed after the segment
+    /// starts, so the inputs in `[0, input_start)` are left to the normal
+    /// entry like the inputs after the segment.
     pub input_start: u32,
     pub input_end: u32,
     pub output_end: u32,
@@ -82,268 +261,1147 @@ pub struct CobuildState {
     pub header_dep_end: u32,
 }
 
+impl CobuildState {
+    /// Encodes the state as 6 little-endian `u32`, in the order of its
+    /// `Display` output, for tools capturing it in binary form. Returns `None`
+    /// if `otx_start_index` doesn't fit in a `u32`.
+    pub fn to_bytes(&self) -> Option<[u8; 24]> {
+        let mut bytes = [0u8; 24];
+        let fields = [
+            self.input_start,
+            self.input_end,
+            self.output_end,
+            self.cell_dep_end,
+            self.header_dep_end,
+            u32::try_from(self.otx_start_index).ok()?,
+        ];
+        for (chunk, field) in bytes.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        Some(bytes)
+    }
+
+    /// Decodes a state encoded by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8; 24]) -> Self {
+        let mut fields = [0u32; 6];
+        for (field, chunk) in fields.iter_mut().zip(bytes.chunks_exact(4)) {
+            *field = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        let [input_start, input_end, output_end, cell_dep_end, header_dep_end, otx_start_index] =
+            fields;
+        Self {
+            otx_start_index: otx_start_index as usize,
+            input_start,
+            input_end,
+            output_end,
+            cell_dep_end,
+            header_dep_end,
+        }
+    }
+}
+
+/// A stable, compact form logged at each OTX, e.g.
+/// `is=0 ie=1 oe=2 cde=0 hde=0 osi=0`, so the range progression of two runs
+/// can be diffed.
+impl fmt::Display for CobuildState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "is={} ie={} oe={} cde={} hde={} osi={}",
+            self.input_start,
+            self.input_end,
+            self.output_end,
+            self.cell_dep_end,
+            self.header_dep_end,
+            self.otx_start_index
+        )
+    }
+}
+
+/// Summary of a `cobuild_entry_detailed` run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CobuildResult {
+    /// Whether cobuild mode is activated. If not, nothing else was checked.
+    pub activated: bool,
+    /// How many times the verifier has been invoked.
+    pub execution_count: usize,
+    /// How many OTX groups contain the current script as an input lock.
+    pub otx_count: usize,
+    /// Whether the normal (SighashAll/SighashAllOnly) entry has been invoked.
+    pub normal_entry_invoked: bool,
+}
+
+/// Default maximum number of OTX witnesses in a transaction. It bounds the
+/// cycles a transaction packed with tiny OTXs can burn in every lock script
+/// group, while leaving room for large batches.
+pub const MAX_OTX_COUNT: usize = 128;
+
+/// Limits applied by `cobuild_entry_with_limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CobuildLimits {
+    /// Maximum number of OTX witnesses, across all OTX segments. Once
+    /// exceeded, `Error::WrongCount` is returned.
+    pub max_otx_count: usize,
+    /// Maximum number of bytes of read buffers of input cells memoized while
+    /// verifying OTXs, since the normal entry reads them again, see
+    /// `CachedTransaction`. Cells which don't fit are read by new cursors on
+    /// every access instead, `0` disables the cache.
+    pub max_cached_cell_bytes: usize,
+}
+
+impl Default for CobuildLimits {
+    fn default() -> Self {
+        Self {
+            max_otx_count: MAX_OTX_COUNT,
+            max_cached_cell_bytes: DEFAULT_CELL_CACHE_LIMIT,
+        }
+    }
+}
+
+/// How the input cells of the current lock script are found in OTXs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptMatch {
+    /// The lock hash of the input equals the current script hash.
+    #[default]
+    ScriptHash,
+    /// The lock of the input has the same `code_hash` and `hash_type` as the
+    /// current script, with any `args`. The seal is still looked up by the
+    /// current script hash.
+    CodeHash,
+}
+
+/// How the seals of the current lock script in an OTX are checked.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OtxStrictness {
+    /// The first matching seal is used, other seals for the same script hash
+    /// are ignored.
+    #[default]
+    Lenient,
+    /// Every `SealPair` of the current script hash in a matched OTX must be
+    /// consumed: one if the script is only in the fixed inputs or only in the
+    /// dynamic cells, two if it's in both. Otherwise `Error::WrongOtx` is returned.
+    ///
+    /// Witnesses after the last OTX segment must also be `SighashAll`,
+    /// `SighashAllOnly` or not a `WitnessLayout` at all. A `WitnessLayout` of
+    /// a type unknown to this crate, which `parse_witness_layouts` reports as
+    /// `None`, is rejected with `Error::WrongWitnessLayout`.
+    Strict,
+}
+
+/// Finds the input cells of the current lock script in OTXs, according to a
+/// `ScriptMatch`.
+pub(crate) struct LockMatcher<'a> {
+    /// hash of the current script, used to find its seals
+    pub script_hash: [u8; 32],
+    pub strictness: OtxStrictness,
+    cache: &'a BTreeMap<[u8; 32], ScriptLocation>,
+    key: [u8; 32],
+}
+
+impl<'a> LockMatcher<'a> {
+    /// Matches input locks by script hash, with `cache` returned by
+    /// `cache_script_hashes` or `cache_script_hashes_with_cell_deps`.
+    pub fn by_script_hash(
+        script_hash: [u8; 32],
+        cache: &'a BTreeMap<[u8; 32], ScriptLocation>,
+    ) -> Self {
+        Self {
+            script_hash,
+            strictness: OtxStrictness::Lenient,
+            cache,
+            key: script_hash,
+        }
+    }
+
+    /// Matches input locks by code hash, with `cache` returned by
+    /// `cache_by_code_hash`.
+    pub fn by_code_hash(
+        script_hash: [u8; 32],
+        code_hash_key: [u8; 32],
+        cache: &'a BTreeMap<[u8; 32], ScriptLocation>,
+    ) -> Self {
+        Self {
+            script_hash,
+            strictness: OtxStrictness::Lenient,
+            cache,
+            key: code_hash_key,
+        }
+    }
+
+    pub fn with_strictness(mut self, strictness: OtxStrictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Returns whether a matching input lock is in `[start_index, end_index)`.
+    pub fn is_included(&self, start_index: usize, end_index: usize) -> bool {
+        is_script_included(
+            self.cache,
+            self.key,
+            ScriptType::InputLock,
+            start_index,
+            end_index,
+        )
+    }
+
+    /// Returns whether a matching output lock or type is in
+    /// `[start_index, end_index)`.
+    pub fn is_included_in_outputs(&self, start_index: usize, end_index: usize) -> bool {
+        [ScriptType::OutputLock, ScriptType::OutputType]
+            .into_iter()
+            .any(|script_type| {
+                is_script_included(self.cache, self.key, script_type, start_index, end_index)
+            })
+    }
+}
+
 /// Attempts to parse all witnesses into a `WitnessLayout` structure. Returns
 /// `None` if parsing is not possible. For instance, parsing fails and returns
 /// `None` if the structure is a `WitnessArgs`. The second return value
 /// indicates whether the cobuild feature is activated (`true`) or not
 /// (`false`).
-fn parse_witness_layouts(
+///
+/// Every parsed layout is verified with `verify(false)`, which rejects tables
+/// carrying unknown trailing fields. An error is returned if any of them is
+/// malformed. See `parse_witness_layouts_with` for the compatible mode.
+///
+/// A `WitnessLayout` member added by a newer cobuild specification is also
+/// returned as `None`. With the `log` feature, it's logged as an unsupported
+/// witness layout type, see `utils::AnyWitnessLayout`.
+pub fn parse_witness_layouts(
     tx: &blockchain::Transaction,
+) -> Result<(Vec<Option<top_level::WitnessLayout>>, bool), Error> {
+    parse_witness_layouts_with(tx, false)
+}
+
+/// Same as `parse_witness_layouts`, verifying every layout with
+/// `verify(compatible)`.
+///
+/// The strict mode (`compatible` is false), used by `cobuild_entry`, rejects
+/// tables carrying unknown trailing fields, so every byte of a witness is
+/// understood by this crate. The compatible mode accepts them, so witnesses
+/// built for a future cobuild specification that extends a table still
+/// parse, but the extra fields are ignored: they aren't checked by this crate
+/// and, outside of the hashed witnesses, not covered by any signature.
+pub fn parse_witness_layouts_with(
+    tx: &blockchain::Transaction,
+    compatible: bool,
 ) -> Result<(Vec<Option<top_level::WitnessLayout>>, bool), Error> {
     let witness_layouts: Vec<Option<top_level::WitnessLayout>> = tx
-        .witnesses()?
+        .witnesses()
+        .at("Transaction.witnesses")?
         .into_iter()
-        .map(|w| top_level::WitnessLayout::try_from(w).ok())
+        .enumerate()
+        .map(|(index, w)| match AnyWitnessLayout::parse(w)? {
+            AnyWitnessLayout::Known(witness_layout) => Some(witness_layout),
+            AnyWitnessLayout::Unknown(id) => {
+                log!("unsupported witness layout type {} at index {}", id, index);
+                None
+            }
+        })
         .collect();
     let mut activated = false;
     for w in witness_layouts.iter().flatten() {
-        w.verify(false)?;
+        w.verify(compatible).at("WitnessLayout")?;
         activated = true;
     }
     Ok((witness_layouts, activated))
 }
 
+/// Parses and verifies a single witness given as bytes, e.g. to validate
+/// untrusted witnesses off-chain before broadcasting. It doesn't use any
+/// syscall and never panics on malformed input, which also makes it a natural
+/// fuzzing entry point.
+///
+/// The layout is verified with `verify(false)`, the strict mode used by
+/// `parse_witness_layouts`: tables carrying unknown trailing fields are
+/// rejected. `verify(true)` is the compatible mode, which accepts them.
+pub fn parse_witness_layout_bytes(data: &[u8]) -> Result<top_level::WitnessLayout, Error> {
+    let witness_layout =
+        top_level::WitnessLayout::try_from(lazy_reader::new_cursor_from_vec(data.to_vec()))?;
+    witness_layout.verify(false).at("WitnessLayout")?;
+    Ok(witness_layout)
+}
+
+/// Same as `parse_witness_layouts`, on the transaction of the current script.
+pub fn parse_tx_witness_layouts() -> Result<(Vec<Option<top_level::WitnessLayout>>, bool), Error> {
+    parse_witness_layouts(&shared_transaction())
+}
+
+/// Returns whether the first witness of the current script group is a cobuild
+/// `WitnessLayout`. `parse_witness_layouts` reports cobuild as activated if any
+/// witness of the transaction is a `WitnessLayout`, but a transaction can mix
+/// cobuild and `WitnessArgs` witnesses of different script groups. A lock
+/// supporting both can use this function to choose per group.
+///
+/// An empty witness isn't a `WitnessLayout`, so it also returns `false` when
+/// the first witness of the group is left empty, e.g. because the inputs of the
+/// group are covered by OTXs, or when the group has no witness at all.
+pub fn current_group_is_cobuild() -> Result<bool, Error> {
+    if let Err(SysError::IndexOutOfBound) = load_witness_len(0, Source::GroupInput) {
+        return Ok(false);
+    }
+    let witness = new_witness(0, Source::GroupInput)?;
+    match top_level::WitnessLayout::try_from(witness) {
+        Ok(witness_layout) => {
+            witness_layout.verify(false).at("WitnessLayout")?;
+            Ok(true)
+        }
+        Err(_) => Ok(false),
+    }
+}
+
+/// Collects the seals of the lock script `script_hash` in all witnesses, as
+/// `(witness_index, seal)` pairs in witness order:
+///
+/// - the seal of the `SighashAll` or `SighashAllOnly` witness at the first
+///   input of the lock script group, if any
+/// - the seal of every `SealPair` matching `script_hash` in every `Otx`
+///
+/// Apart from the molecule encoding of the witnesses, it doesn't verify
+/// anything: neither the seals nor the OTX ranges are checked, and the seals
+/// are not bound to any signing message hash. It lets a
+/// lock implement its own verification policy, e.g. aggregating the seals of
+/// OTX and normal groups, on top of `cobuild_entry` or instead of it.
+pub fn collect_seals_for(script_hash: [u8; 32]) -> Result<Vec<(usize, Vec<u8>)>, Error> {
+    let (witness_layouts, _) = parse_tx_witness_layouts()?;
+    let first_input = QueryIter::new(load_cell_lock_hash, Source::Input)
+        .position(|lock_hash| ct_eq_32(&lock_hash, &script_hash));
+
+    let mut seals = Vec::new();
+    for (index, witness_layout) in witness_layouts.iter().enumerate() {
+        match witness_layout {
+            Some(top_level::WitnessLayout::SighashAll(s)) if Some(index) == first_input => {
+                seals.push((index, s.seal()?.try_into()?));
+            }
+            Some(top_level::WitnessLayout::SighashAllOnly(s)) if Some(index) == first_input => {
+                seals.push((index, s.seal()?.try_into()?));
+            }
+            Some(top_level::WitnessLayout::Otx(otx)) => {
+                let seal_pairs = otx.seals()?;
+                for i in 0..seal_pairs.len()? {
+                    let seal_pair = seal_pairs.get(i)?;
+                    if ct_eq_32(&seal_pair.script_hash()?, &script_hash) {
+                        seals.push((index, seal_pair.seal()?.try_into()?));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(seals)
+}
+
+/// A `(script_hash, signing_message_hash)` pair of `all_signing_message_hashes`.
+pub type LockSigningMessageHash = ([u8; 32], [u8; 32]);
+
+/// Returns the signing message hashes every input lock of the transaction
+/// would verify in `cobuild_entry`, as `(script_hash, smh)` pairs: the locks
+/// are in ascending order of script hash, and the hashes of each lock are in
+/// the order `cobuild_entry` computes them, i.e. one per OTX signing range in
+/// witness order, then the `SighashAll` or `SighashAllOnly` one if the lock
+/// has inputs outside of the OTXs.
+///
+/// It's meant for observability, e.g. to show what each signer has to sign.
+/// OTXs are checked like in `cobuild_entry`, but no seal is looked up or
+/// verified, so it works before the transaction is signed. Returns an empty
+/// vector when cobuild isn't activated.
+pub fn all_signing_message_hashes() -> Result<Vec<LockSigningMessageHash>, Error> {
+    let cached_tx = CachedTransaction::new();
+    let (witness_layouts, activated) = parse_witness_layouts(cached_tx.transaction())?;
+    if !activated {
+        return Ok(Vec::new());
+    }
+    let otx_starts = fetch_otx_start(&witness_layouts)?;
+    let script_hashes_cache = cache_script_hashes_with_cell_deps();
+    let inputs_len = cached_tx.transaction().raw()?.inputs()?.len()?;
+    // the SighashAll signing message hash is the same for all locks
+    let mut normal_smh = None;
+
+    let mut hashes = Vec::new();
+    for (script_hash, location) in &script_hashes_cache {
+        if location.input_lock.is_empty() {
+            continue;
+        }
+        let mut input_ranges = Vec::new();
+        if !otx_starts.is_empty() {
+            let mut collector = OtxSmhCollector {
+                cached_tx: &cached_tx,
+                hashes: Vec::new(),
+            };
+            input_ranges = verify_otx_segments(
+                &mut collector,
+                &cached_tx,
+                &witness_layouts,
+                otx_starts.clone(),
+                &script_hashes_cache,
+                &LockMatcher::by_script_hash(*script_hash, &script_hashes_cache),
+                &CobuildLimits::default(),
+            )?
+            .input_ranges;
+            hashes.extend(collector.hashes.into_iter().map(|smh| (*script_hash, smh)));
+        }
+        if input_lock_outside_otxs(&script_hashes_cache, script_hash, &input_ranges, inputs_len) {
+            let smh = match normal_smh {
+                Some(smh) => smh,
+                None => {
+                    let message = message_of(&witness_layouts)?;
+                    let smh = generate_signing_message_hash_cached(&cached_tx, &message)?;
+                    normal_smh = Some(smh);
+                    smh
+                }
+            };
+            hashes.push((*script_hash, smh));
+        }
+    }
+    Ok(hashes)
+}
+
 /// Serves as the primary entry point for a lock script supporting cobuild.
 /// Operates in conjunction with the `Callback` trait. For integration
 /// instructions into cobuild, refer to the crate documentation.
+///
+/// Returns whether cobuild is activated. See `cobuild_entry_detailed` for more
+/// details about the verification.
 pub fn cobuild_entry<F: Callback>(verifier: F) -> Result<bool, Error> {
-    let tx = new_transaction();
+    Ok(cobuild_entry_detailed(verifier)?.activated)
+}
+
+/// Entry point for a script deployed both as a lock and as a type script. The
+/// role is detected from the current script hash, with the input locks of the
+/// transaction:
+///
+/// - if any input cell uses the current script as lock, it runs the lock flow
+///   of `cobuild_entry` with `verifier`
+/// - otherwise, the script only runs as a type script: the message of the
+///   `SighashAll` witness, if any, is checked with `utils::check_message` and
+///   `verifier` is never invoked
+///
+/// The rule is based on the script hash only, so when the same script is both
+/// an input lock and a type script of the transaction, its type script group
+/// also runs the lock flow. Returns whether cobuild is activated.
+pub fn cobuild_auto_entry<F: Callback>(verifier: F) -> Result<bool, Error> {
+    let script_hash = load_script_hash()?;
+    let is_input_lock = QueryIter::new(load_cell_lock_hash, Source::Input)
+        .any(|lock_hash| ct_eq_32(&lock_hash, &script_hash));
+    if is_input_lock {
+        return cobuild_entry(verifier);
+    }
+
+    let (witness_layouts, activated) = parse_tx_witness_layouts()?;
+    if let Some(message) = message_of(&witness_layouts)? {
+        // actions may name the scripts of cell deps
+        check_message(&cache_script_hashes_with_cell_deps(), message)?;
+    }
+    Ok(activated)
+}
+
+/// Adapts a closure to the `Callback` trait.
+pub(crate) struct FnCallback<F>(pub F);
+
+impl<F: FnMut(&[u8], &[u8; 32]) -> Result<(), Error>> Callback for FnCallback<F> {
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        (self.0)(seal, signing_message_hash)
+    }
+}
+
+/// Same as `cobuild_entry`, but takes a closure as verifier instead of a
+/// `Callback` implementation. The closure receives the seal and the signing
+/// message hash.
+pub fn cobuild_entry_fn<F: FnMut(&[u8], &[u8; 32]) -> Result<(), Error>>(
+    f: F,
+) -> Result<bool, Error> {
+    cobuild_entry(FnCallback(f))
+}
+
+/// Same as `cobuild_entry`, but returns a `CobuildResult` summarizing the
+/// verification. A lock script can use it to enforce, for example, that its
+/// seal has been checked at least once.
+pub fn cobuild_entry_detailed<F: Callback>(verifier: F) -> Result<CobuildResult, Error> {
+    Ok(cobuild_entry_inner(IgnoreMessage(verifier), &EntryOptions::default())?.result)
+}
+
+/// Same as `cobuild_entry`, but works with a `MessageCallback` verifier, which
+/// also receives the message of each OTX group or of the `SighashAll` witness.
+pub fn cobuild_entry_with_message<F: MessageCallback>(verifier: F) -> Result<bool, Error> {
+    Ok(cobuild_entry_inner(verifier, &EntryOptions::default())?
+        .result
+        .activated)
+}
+
+/// Same as `cobuild_entry`, but with custom `limits` instead of the default
+/// ones.
+pub fn cobuild_entry_with_limits<F: Callback>(
+    verifier: F,
+    limits: CobuildLimits,
+) -> Result<bool, Error> {
+    let options = EntryOptions {
+        limits,
+        ..Default::default()
+    };
+    Ok(cobuild_entry_inner(IgnoreMessage(verifier), &options)?
+        .result
+        .activated)
+}
+
+/// Same as `cobuild_entry`, but the input cells of the current lock script in
+/// OTXs are found with `script_match`. With `ScriptMatch::CodeHash`, an OTX
+/// containing an input locked by the same code with different args is also
+/// verified by the current script. Inputs outside of OTXs are always matched
+/// by script hash, since they belong to the script group.
+pub fn cobuild_entry_with_script_match<F: Callback>(
+    verifier: F,
+    script_match: ScriptMatch,
+) -> Result<bool, Error> {
+    let options = EntryOptions {
+        script_match,
+        ..Default::default()
+    };
+    Ok(cobuild_entry_inner(IgnoreMessage(verifier), &options)?
+        .result
+        .activated)
+}
+
+/// Same as `cobuild_entry`, but the seals of the current lock script in OTXs
+/// are checked according to `strictness`. With `OtxStrictness::Strict`, an OTX
+/// carrying extra seals for the current script, which are never verified, is
+/// rejected, and so is an unknown `WitnessLayout` after the OTXs.
+pub fn cobuild_entry_strict<F: Callback>(
+    verifier: F,
+    strictness: OtxStrictness,
+) -> Result<bool, Error> {
+    let options = EntryOptions {
+        strictness,
+        ..Default::default()
+    };
+    Ok(cobuild_entry_inner(IgnoreMessage(verifier), &options)?
+        .result
+        .activated)
+}
+
+/// Summary of a `cobuild_entry_partial` run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PartialCobuildResult {
+    pub result: CobuildResult,
+    /// Witness indices of the OTX groups of the current script whose seal is
+    /// still empty, in witness order.
+    pub unsigned_otx_indices: Vec<usize>,
+}
+
+/// Same as `cobuild_entry_detailed`, for OTXs which are not fully signed yet,
+/// e.g. while several parties build a transaction together. An empty seal of
+/// the current script in an OTX is treated as "not signed yet": the verifier
+/// isn't invoked for it and the witness index of the OTX is returned in
+/// `unsigned_otx_indices`. The cells of unsigned OTXs are still accounted for,
+/// so they are not verified by the `SighashAll` or `SighashAllOnly` witness
+/// either. A missing seal is still an error.
+///
+/// It must not be used to unlock cells: a transaction is only authorized once
+/// `unsigned_otx_indices` is empty. `cobuild_entry` never skips empty seals.
+pub fn cobuild_entry_partial<F: Callback>(verifier: F) -> Result<PartialCobuildResult, Error> {
+    let options = EntryOptions {
+        allow_unsigned: true,
+        ..Default::default()
+    };
+    cobuild_entry_inner(IgnoreMessage(verifier), &options)
+}
+
+/// Options of `cobuild_entry_inner`, each set by one of the entry points.
+#[derive(Default)]
+struct EntryOptions {
+    limits: CobuildLimits,
+    script_match: ScriptMatch,
+    strictness: OtxStrictness,
+    /// skip the OTX groups whose seal is empty, see `cobuild_entry_partial`
+    allow_unsigned: bool,
+}
+
+/// Returns the number of cells or deps of an OTX, `fixed + dynamic`. The
+/// counts come from untrusted witnesses and an overflow would wrap the range
+/// around and let the OTX revisit cells consumed by previous ones, so it's
+/// rejected with `Error::WrongCount`.
+fn otx_cell_count(fixed: u32, dynamic: u32) -> Result<u32, Error> {
+    fixed.checked_add(dynamic).ok_or_else(|| {
+        log!("OTX count overflows: {} + {}", fixed, dynamic);
+        Error::WrongCount
+    })
+}
+
+fn cobuild_entry_inner<F: MessageCallback>(
+    verifier: F,
+    options: &EntryOptions,
+) -> Result<PartialCobuildResult, Error> {
+    #[cfg(not(feature = "paranoid"))]
+    let mut verifier = verifier;
+    #[cfg(feature = "paranoid")]
+    let mut verifier = CollisionCheck {
+        verifier,
+        hashes: Vec::new(),
+    };
+    let tx = shared_transaction();
     let raw_tx = tx.raw()?;
     let (witness_layouts, cobuild_activated) = parse_witness_layouts(&tx)?;
-    // Legacy Flow Handling
+    // Legacy Flow Handling. This also covers transactions without witnesses,
+    // so `witness_layouts` is never empty below.
     if !cobuild_activated {
-        return Ok(false);
+        return Ok(PartialCobuildResult::default());
     }
 
     let current_script_hash = load_script_hash()?;
-    let script_hashes_cache = cache_script_hashes();
+    let script_hashes_cache = cache_script_hashes_with_cell_deps();
     // step 2
     // step 4
-    let (otx_start, otx_start_index) = fetch_otx_start(&witness_layouts)?;
-    if otx_start.is_none() {
+    let otx_starts = fetch_otx_start(&witness_layouts)?;
+    if otx_starts.is_empty() {
         // step 3
         log!("No otx detected");
-        cobuild_normal_entry(verifier, &script_hashes_cache)?;
-        return Ok(true);
-    }
-    let otx_start = otx_start.unwrap();
-
-    let start_input_cell: u32 = otx_start.start_input_cell()?;
-    let start_output_cell: u32 = otx_start.start_output_cell()?;
-    let start_cell_deps: u32 = otx_start.start_cell_deps()?;
-    let start_header_deps: u32 = otx_start.start_header_deps()?;
-    // step 5
-    let mut state = CobuildState {
-        otx_start_index,
-        input_start: start_input_cell,
-        input_end: start_input_cell,
-        output_end: start_output_cell,
-        cell_dep_end: start_cell_deps,
-        header_dep_end: start_header_deps,
-    };
+        cobuild_normal_entry_cached(
+            &mut verifier,
+            &CachedTransaction::from_transaction(tx.as_ref().clone(), 0),
+            message_of(&witness_layouts)?,
+            &script_hashes_cache,
+        )?;
+        return Ok(PartialCobuildResult {
+            result: CobuildResult {
+                activated: true,
+                execution_count: 1,
+                otx_count: 0,
+                normal_entry_invoked: true,
+            },
+            unsigned_otx_indices: Vec::new(),
+        });
+    }
 
-    let mut execution_count: usize = 0;
-    let mut otx_count = 0;
-    log!("state: {:?}", state);
-    log!("Otx starts at index {}(inclusive)", otx_start_index + 1);
-    // this index is always pointing to the current processing OTX witness.
-    let mut otx_witness_end_index = otx_start_index;
-    for witness_index in otx_start_index + 1..witness_layouts.len() {
-        otx_witness_end_index = witness_index;
-        let witness = witness_layouts.get(witness_index).unwrap();
-        if witness.is_none() {
-            // step 6, not WitnessLayoutOtx
-            break;
+    let code_hash_cache;
+    let lock_matcher = match options.script_match {
+        ScriptMatch::ScriptHash => {
+            LockMatcher::by_script_hash(current_script_hash, &script_hashes_cache)
         }
-        match witness {
-            Some(top_level::WitnessLayout::Otx(ref otx)) => {
-                otx_count += 1;
-
-                let flag: u8 = otx.flag()?;
-                let otx_configs: OtxDynamicConfigs = flag.try_into()?;
-
-                let fixed_input_cells: u32 = otx.fixed_input_cells()?;
-                let fixed_output_cells: u32 = otx.fixed_output_cells()?;
-                let fixed_cell_deps: u32 = otx.fixed_cell_deps()?;
-                let fixed_header_deps: u32 = otx.fixed_header_deps()?;
-
-                if fixed_input_cells == 0
-                    && fixed_output_cells == 0
-                    && fixed_cell_deps == 0
-                    && fixed_header_deps == 0
-                {
-                    return Err(Error::WrongCount);
-                }
-
-                let dynamic_input_cells: u32 = otx.dynamic_input_cells()?;
-                let dynamic_output_cells: u32 = otx.dynamic_output_cells()?;
-                let dynamic_cell_deps: u32 = otx.dynamic_cell_deps()?;
-                let dynamic_header_deps: u32 = otx.dynamic_header_deps()?;
-
-                if !otx_configs.dynamic_inputs && dynamic_input_cells != 0
-                    || !otx_configs.dynamic_outputs && dynamic_output_cells != 0
-                    || !otx_configs.dynamic_cell_deps && dynamic_cell_deps != 0
-                    || !otx_configs.dynamic_header_deps && dynamic_header_deps != 0
-                {
-                    return Err(Error::WrongCount);
-                }
+        ScriptMatch::CodeHash => {
+            code_hash_cache = cache_by_code_hash();
+            LockMatcher::by_code_hash(
+                current_script_hash,
+                code_hash_key(&load_script()?),
+                &code_hash_cache,
+            )
+        }
+    }
+    .with_strictness(options.strictness);
+    // input cells of OTXs are read again by the normal entry, load them once
+    let cached_tx = CachedTransaction::from_transaction(
+        tx.as_ref().clone(),
+        options.limits.max_cached_cell_bytes,
+    );
+    let mut seal_verifier = OtxSealVerifier::new(&mut verifier, &cached_tx, current_script_hash);
+    if options.allow_unsigned {
+        seal_verifier.unsigned = Some(Vec::new());
+    }
+    let otx_verification = verify_otx_segments(
+        &mut seal_verifier,
+        &cached_tx,
+        &witness_layouts,
+        otx_starts,
+        &script_hashes_cache,
+        &lock_matcher,
+        &options.limits,
+    )?;
+    let unsigned_otx_indices = seal_verifier.unsigned.unwrap_or_default();
+    if options.strictness == OtxStrictness::Strict {
+        check_trailing_witnesses(&tx, &witness_layouts, otx_verification.witness_end)?;
+    }
+    let mut execution_count = otx_verification.execution_count;
+    let otx_input_ranges = otx_verification.input_ranges;
 
-                check_message(&script_hashes_cache, otx.message()?)?;
+    // step 8
+    let found = input_lock_outside_otxs(
+        &script_hashes_cache,
+        &current_script_hash,
+        &otx_input_ranges,
+        raw_tx.inputs()?.len()?,
+    );
+    if found {
+        execution_count += 1;
+        log!("extra callback is invoked");
+        cobuild_normal_entry_cached(
+            &mut verifier,
+            &cached_tx,
+            message_of(&witness_layouts)?,
+            &script_hashes_cache,
+        )?;
+    }
+    log!("execution_count = {}", execution_count);
+    Ok(PartialCobuildResult {
+        result: CobuildResult {
+            activated: true,
+            execution_count,
+            otx_count: otx_verification.matched_witness_indices.len(),
+            normal_entry_invoked: found,
+        },
+        unsigned_otx_indices,
+    })
+}
 
-                let lock_hash_existing_in_fixed = is_script_included(
-                    &script_hashes_cache,
-                    current_script_hash,
-                    ScriptType::InputLock,
-                    state.input_end as usize,
-                    (state.input_end + fixed_input_cells) as usize,
-                );
+/// Checks that the witnesses from `witness_end` on, after the last OTX
+/// segment, are no `WitnessLayout` of a type unknown to this crate.
+fn check_trailing_witnesses(
+    tx: &blockchain::Transaction,
+    witness_layouts: &[Option<top_level::WitnessLayout>],
+    witness_end: usize,
+) -> Result<(), Error> {
+    let witnesses = tx.witnesses().at("Transaction.witnesses")?;
+    for (index, witness_layout) in witness_layouts.iter().enumerate().skip(witness_end) {
+        if witness_layout.is_some() {
+            continue;
+        }
+        if let Some(AnyWitnessLayout::Unknown(id)) = AnyWitnessLayout::parse(witnesses.get(index)?)
+        {
+            log!(
+                "unknown witness layout type {} at index {} after the OTXs",
+                id,
+                index
+            );
+            return Err(Error::WrongWitnessLayout);
+        }
+    }
+    Ok(())
+}
 
-                let lock_hash_existing_in_dynamic = is_script_included(
-                    &script_hashes_cache,
-                    current_script_hash,
-                    ScriptType::InputLock,
-                    (state.input_end + fixed_input_cells) as usize,
-                    (state.input_end + fixed_input_cells + dynamic_input_cells) as usize,
-                );
+/// Returns whether `script_hash` locks an input cell outside of every OTX
+/// input range `[is, ie)`, i.e. whether it needs the normal entry besides its
+/// OTXs.
+pub(crate) fn input_lock_outside_otxs(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    script_hash: &[u8; 32],
+    otx_input_ranges: &[(u32, u32)],
+    inputs_len: usize,
+) -> bool {
+    // the OTX segments don't overlap, so they consume all inputs iff their
+    // lengths add up to the inputs count
+    let otx_inputs_count: usize = otx_input_ranges
+        .iter()
+        .map(|(is, ie)| (ie - is) as usize)
+        .sum();
+    otx_inputs_count < inputs_len
+        && script_hashes_cache
+            .get(script_hash)
+            .is_some_and(|location| {
+                // if is == ie, it is always true
+                location.input_lock.iter().any(|index| {
+                    otx_input_ranges
+                        .iter()
+                        .all(|(is, ie)| *index < *is as usize || *index >= *ie as usize)
+                })
+            })
+}
 
-                if !lock_hash_existing_in_fixed && !lock_hash_existing_in_dynamic {
-                    state.input_end += fixed_input_cells + dynamic_input_cells;
-                    state.output_end += fixed_output_cells + dynamic_output_cells;
-                    state.cell_dep_end += fixed_cell_deps + dynamic_cell_deps;
-                    state.header_dep_end += fixed_header_deps + dynamic_header_deps;
-                    continue;
-                }
+/// Outcome of `verify_otx_segments`.
+pub(crate) struct OtxVerification {
+    /// input cells [is, ie) covered by each OTX segment
+    pub input_ranges: Vec<(u32, u32)>,
+    /// how many times the verifier has been invoked
+    pub execution_count: usize,
+    /// witness indices of the OTX groups containing the current script as an
+    /// input lock, in witness order
+    pub matched_witness_indices: Vec<usize>,
+    /// index of the first witness after the last OTX segment
+    pub witness_end: usize,
+}
 
-                if lock_hash_existing_in_fixed {
-                    // step 6.e
-                    let fixed_smh = generate_otx_smh(
-                        &raw_tx,
-                        otx.message()?,
-                        OtxSigningRange {
-                            input_start: state.input_end,
-                            inputs_count: fixed_input_cells,
-                            output_start: state.output_end,
-                            outputs_count: fixed_output_cells,
-                            cell_dep_start: state.cell_dep_end,
-                            cell_deps_count: fixed_cell_deps,
-                            header_dep_start: state.header_dep_end,
-                            header_deps_count: fixed_header_deps,
-                        },
-                    )?;
-                    // step 6.f
-                    let mut seal_found = false;
-                    for index in 0..otx.seals()?.len()? {
-                        let seal_pair = otx.seals()?.get(index)?;
-                        if seal_pair.script_hash()? == current_script_hash.as_slice() {
-                            let seal: Vec<u8> = seal_pair.seal()?.try_into()?;
-                            log!("invoke OTX verifier");
-                            verifier.invoke(&seal, &fixed_smh)?;
-                            seal_found = true;
-                            execution_count += 1;
-                            break;
-                            // duplicated seals are ignored
-                        }
-                    }
+/// Handles each signing range of the current lock script found by
+/// `verify_otx_segments`.
+pub(crate) trait OtxRangeHandler {
+    /// `otx_context.dynamic` tells whether `signing_range` covers the dynamic
+    /// part of `otx` too. Returns whether the verifier has been invoked.
+    fn handle(
+        &mut self,
+        otx: &basic::Otx,
+        signing_range: OtxSigningRange,
+        otx_context: &OtxContext,
+    ) -> Result<bool, Error>;
+}
 
-                    if !seal_found {
-                        log!("seal can't be found");
-                        return Err(Error::NoSealFound);
-                    }
-                }
+/// Verifies the seal of `script_hash` against the signing message hash of
+/// each range (steps 6.e and 6.f).
+pub(crate) struct OtxSealVerifier<'a, F> {
+    verifier: &'a mut F,
+    cached_tx: &'a CachedTransaction,
+    script_hash: [u8; 32],
+    /// When set, empty seals are skipped and the witness indices of their
+    /// OTXs are collected here.
+    pub unsigned: Option<Vec<usize>>,
+}
 
-                if lock_hash_existing_in_dynamic {
-                    // step 6.e
-                    let dynamic_smh = generate_otx_smh(
-                        &raw_tx,
-                        otx.message()?,
-                        OtxSigningRange {
-                            input_start: state.input_end,
-                            inputs_count: fixed_input_cells + dynamic_input_cells,
-                            output_start: state.output_end,
-                            outputs_count: fixed_output_cells,
-                            cell_dep_start: state.cell_dep_end,
-                            cell_deps_count: fixed_cell_deps,
-                            header_dep_start: state.header_dep_end,
-                            header_deps_count: fixed_header_deps,
-                        },
-                    )?;
-                    // step 6.f
-                    let mut seal_found = false;
-                    for index in (0..otx.seals()?.len()?).rev() {
-                        let seal_pair = otx.seals()?.get(index)?;
-                        if seal_pair.script_hash()? == current_script_hash.as_slice() {
-                            let seal: Vec<u8> = seal_pair.seal()?.try_into()?;
-                            log!("invoke OTX verifier");
-                            verifier.invoke(&seal, &dynamic_smh)?;
-                            seal_found = true;
-                            execution_count += 1;
-                            break;
-                            // duplicated seals are ignored
-                        }
-                    }
+impl<'a, F> OtxSealVerifier<'a, F> {
+    pub fn new(
+        verifier: &'a mut F,
+        cached_tx: &'a CachedTransaction,
+        script_hash: [u8; 32],
+    ) -> Self {
+        Self {
+            verifier,
+            cached_tx,
+            script_hash,
+            unsigned: None,
+        }
+    }
+}
 
-                    if !seal_found {
-                        log!("seal can't be found");
-                        return Err(Error::NoSealFound);
-                    }
+impl<F: MessageCallback> OtxRangeHandler for OtxSealVerifier<'_, F> {
+    fn handle(
+        &mut self,
+        otx: &basic::Otx,
+        signing_range: OtxSigningRange,
+        otx_context: &OtxContext,
+    ) -> Result<bool, Error> {
+        // step 6.f. The seal for the dynamic part is searched from the back.
+        let seal = find_seal(&otx.seals()?, &self.script_hash, otx_context.dynamic)?;
+        if let Some(unsigned) = &mut self.unsigned {
+            if seal.is_empty() {
+                log!(
+                    "OTX at index {} is not signed yet",
+                    otx_context.witness_index
+                );
+                if unsigned.last() != Some(&otx_context.witness_index) {
+                    unsigned.push(otx_context.witness_index);
                 }
-
-                // step 6.h
-                state.input_end += fixed_input_cells + dynamic_input_cells;
-                state.output_end += fixed_output_cells + dynamic_output_cells;
-                state.cell_dep_end += fixed_cell_deps + dynamic_cell_deps;
-                state.header_dep_end += fixed_header_deps + dynamic_header_deps;
-            }
-            _ => {
-                break;
+                return Ok(false);
             }
         }
-    } // end of step 6 loop
+        // step 6.e
+        let smh = generate_otx_smh_cached(
+            self.cached_tx,
+            &self.cached_tx.transaction().raw()?,
+            otx.message()?,
+            signing_range,
+        )?;
+        log!("invoke OTX verifier");
+        self.verifier
+            .invoke_otx(&seal, &smh, Some(&otx.message()?), otx_context)?;
+        Ok(true)
+    }
+}
 
-    // step 7
-    // after the loop, the j points to the first non OTX witness or out of bounds
-    let first_non_otx_witness_index = if otx_witness_end_index == (witness_layouts.len() - 1) {
-        witness_layouts.len()
-    } else {
-        otx_witness_end_index
-    };
-    log!(
-        "the first non OTX witness is at index {}",
-        first_non_otx_witness_index
-    );
-    for loop_index in 0..witness_layouts.len() {
-        // [0, i) [j, +infinity)
-        if loop_index < otx_start_index || loop_index >= first_non_otx_witness_index {
-            if let Some(Some(top_level::WitnessLayout::Otx(_))) = &witness_layouts.get(loop_index) {
+/// Collects the signing ranges, without verifying anything.
+impl OtxRangeHandler for Vec<OtxSigningRange> {
+    fn handle(
+        &mut self,
+        _otx: &basic::Otx,
+        signing_range: OtxSigningRange,
+        _otx_context: &OtxContext,
+    ) -> Result<bool, Error> {
+        self.push(signing_range);
+        Ok(false)
+    }
+}
+
+/// Computes the signing message hash of each range, without verifying
+/// anything.
+struct OtxSmhCollector<'a> {
+    cached_tx: &'a CachedTransaction,
+    hashes: Vec<[u8; 32]>,
+}
+
+impl OtxRangeHandler for OtxSmhCollector<'_> {
+    fn handle(
+        &mut self,
+        otx: &basic::Otx,
+        signing_range: OtxSigningRange,
+        _otx_context: &OtxContext,
+    ) -> Result<bool, Error> {
+        self.hashes.push(generate_otx_smh_cached(
+            self.cached_tx,
+            &self.cached_tx.transaction().raw()?,
+            otx.message()?,
+            signing_range,
+        )?);
+        Ok(false)
+    }
+}
+
+/// Steps 5 to 7 of the cobuild flow: verifies all OTX segments starting at
+/// `otx_starts` for the lock matched by `lock_matcher`, passing each signing
+/// range of the lock to `handler`. It's shared by `cobuild_entry`,
+/// `otx::verify_otx_message` and `otx::otx_signing_ranges_for`, so all of them
+/// walk OTXs in exactly the same way. `script_hashes_cache` is used to check
+/// the messages.
+pub(crate) fn verify_otx_segments<H: OtxRangeHandler>(
+    handler: &mut H,
+    cached_tx: &CachedTransaction,
+    witness_layouts: &[Option<top_level::WitnessLayout>],
+    otx_starts: Vec<(basic::OtxStart, usize)>,
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    lock_matcher: &LockMatcher,
+    limits: &CobuildLimits,
+) -> Result<OtxVerification, Error> {
+    let raw_tx = &cached_tx.transaction().raw()?;
+    let inputs_len = raw_tx.inputs()?.len()?;
+    let outputs_len = raw_tx.outputs()?.len()?;
+    let cell_deps_len = raw_tx.cell_deps()?.len()?;
+    let header_deps_len = raw_tx.header_deps()?.len()?;
+
+    let mut execution_count: usize = 0;
+    let mut otx_count = 0;
+    let mut matched_witness_indices = Vec::new();
+    // input cells [is, ie) covered by each OTX segment
+    let mut otx_input_ranges: Vec<(u32, u32)> = Vec::new();
+    // witnesses (i, j) of each OTX segment: the OtxStart is at i, and the OTX
+    // witnesses are in (i, j)
+    let mut otx_witness_ranges: Vec<(usize, usize)> = Vec::new();
+    // state after the previous OTX segment. A segment can't start before the
+    // cells and deps consumed by the previous one.
+    let mut previous_state: Option<CobuildState> = None;
+    for (otx_start, otx_start_index) in otx_starts {
+        let start_input_cell: u32 = otx_start
+            .start_input_cell()
+            .at("OtxStart.start_input_cell")?;
+        let start_output_cell: u32 = otx_start
+            .start_output_cell()
+            .at("OtxStart.start_output_cell")?;
+        let start_cell_deps: u32 = otx_start.start_cell_deps().at("OtxStart.start_cell_deps")?;
+        let start_header_deps: u32 = otx_start
+            .start_header_deps()
+            .at("OtxStart.start_header_deps")?;
+        // the start indices must point into the transaction
+        for (name, start, len) in [
+            ("start_input_cell", start_input_cell, inputs_len),
+            ("start_output_cell", start_output_cell, outputs_len),
+            ("start_cell_deps", start_cell_deps, cell_deps_len),
+            ("start_header_deps", start_header_deps, header_deps_len),
+        ] {
+            if start as usize > len {
                 log!(
-                    "WrongWitnessLayout at index = {} (i = {}, j = {}, otx_count = {})",
-                    loop_index,
-                    otx_start_index,
-                    first_non_otx_witness_index,
-                    otx_count
+                    "{} = {} exceeds the transaction length {}",
+                    name,
+                    start,
+                    len
                 );
-                return Err(Error::WrongWitnessLayout);
+                return Err(Error::WrongOtxStart);
             }
         }
-    }
-    // step 8
-    let mut found = false;
-    for index in 0..raw_tx.inputs()?.len()? {
-        // scan all input cell in [0, is) and [ie, +infinity)
-        // if is == ie, it is always true
-        if index < state.input_start as usize || index >= state.input_end as usize {
-            let hash = load_cell_lock_hash(index, Source::Input)?;
-            if hash == current_script_hash {
-                found = true;
-                break;
+        // step 5
+        let mut state = CobuildState {
+            otx_start_index,
+            input_start: start_input_cell,
+            input_end: start_input_cell,
+            output_end: start_output_cell,
+            cell_dep_end: start_cell_deps,
+            header_dep_end: start_header_deps,
+        };
+        if let Some(previous_state) = &previous_state {
+            if state.input_end < previous_state.input_end
+                || state.output_end < previous_state.output_end
+                || state.cell_dep_end < previous_state.cell_dep_end
+                || state.header_dep_end < previous_state.header_dep_end
+            {
+                log!(
+                    "OtxStart at index {} overlaps the previous OTX segment",
+                    otx_start_index
+                );
+                return Err(Error::WrongOtxStart);
             }
         }
+
+        log!("state: {}", state);
+        log!("Otx starts at index {}(inclusive)", otx_start_index + 1);
+        // after the loop, this index points to the first non OTX witness or
+        // out of bounds
+        let mut first_non_otx_witness_index = otx_start_index + 1;
+        for (witness_index, witness) in witness_layouts.iter().enumerate().skip(otx_start_index + 1)
+        {
+            let otx = match witness.as_otx() {
+                Some(otx) => otx,
+                // step 6, not WitnessLayoutOtx
+                None => break,
+            };
+            first_non_otx_witness_index = witness_index + 1;
+            otx_count += 1;
+            if otx_count > limits.max_otx_count {
+                log!("more than {} OTXs", limits.max_otx_count);
+                return Err(Error::WrongCount);
+            }
+
+            let flag: u8 = otx.flag().at("Otx.flag")?;
+            let otx_configs: OtxDynamicConfigs = flag.try_into()?;
+
+            let fixed_input_cells: u32 = otx.fixed_input_cells().at("Otx.fixed_input_cells")?;
+            let fixed_output_cells: u32 = otx.fixed_output_cells().at("Otx.fixed_output_cells")?;
+            let fixed_cell_deps: u32 = otx.fixed_cell_deps().at("Otx.fixed_cell_deps")?;
+            let fixed_header_deps: u32 = otx.fixed_header_deps().at("Otx.fixed_header_deps")?;
+
+            if fixed_input_cells == 0
+                && fixed_output_cells == 0
+                && fixed_cell_deps == 0
+                && fixed_header_deps == 0
+            {
+                return Err(Error::WrongCount);
+            }
+
+            let dynamic_input_cells: u32 =
+                otx.dynamic_input_cells().at("Otx.dynamic_input_cells")?;
+            let dynamic_output_cells: u32 =
+                otx.dynamic_output_cells().at("Otx.dynamic_output_cells")?;
+            let dynamic_cell_deps: u32 = otx.dynamic_cell_deps().at("Otx.dynamic_cell_deps")?;
+            let dynamic_header_deps: u32 =
+                otx.dynamic_header_deps().at("Otx.dynamic_header_deps")?;
+
+            if !otx_configs.dynamic_inputs && dynamic_input_cells != 0
+                || !otx_configs.dynamic_outputs && dynamic_output_cells != 0
+                || !otx_configs.dynamic_cell_deps && dynamic_cell_deps != 0
+                || !otx_configs.dynamic_header_deps && dynamic_header_deps != 0
+            {
+                return Err(Error::WrongCount);
+            }
+
+            // the OTX can't claim more cells or deps than the transaction has,
+            // checked before any of its seals is verified
+            let [input_end, output_end, cell_dep_end, header_dep_end] = validate_otx_totals(
+                [
+                    state.input_end,
+                    state.output_end,
+                    state.cell_dep_end,
+                    state.header_dep_end,
+                ],
+                &[[
+                    otx_cell_count(fixed_input_cells, dynamic_input_cells)?,
+                    otx_cell_count(fixed_output_cells, dynamic_output_cells)?,
+                    otx_cell_count(fixed_cell_deps, dynamic_cell_deps)?,
+                    otx_cell_count(fixed_header_deps, dynamic_header_deps)?,
+                ]],
+                [inputs_len, outputs_len, cell_deps_len, header_deps_len],
+            )?;
+
+            check_message(script_hashes_cache, otx.message()?)?;
+
+            let otx_context = OtxContext {
+                witness_index,
+                flag,
+                fixed_input_cells,
+                fixed_output_cells,
+                fixed_cell_deps,
+                fixed_header_deps,
+                dynamic_input_cells,
+                dynamic_output_cells,
+                dynamic_cell_deps,
+                dynamic_header_deps,
+                input_start: state.input_end,
+                output_start: state.output_end,
+                cell_dep_start: state.cell_dep_end,
+                header_dep_start: state.header_dep_end,
+                dynamic: false,
+            };
+
+            let lock_hash_existing_in_fixed = lock_matcher.is_included(
+                state.input_end as usize,
+                (state.input_end + fixed_input_cells) as usize,
+            );
+
+            // without dynamic sections, the dynamic inputs are always empty.
+            // Outputs appended dynamically with the current script as lock or
+            // type also need its signature over the dynamic range, even if
+            // none of its inputs is in it.
+            let lock_hash_existing_in_dynamic = otx_configs.any_dynamic()
+                && (lock_matcher.is_included(
+                    (state.input_end + fixed_input_cells) as usize,
+                    input_end as usize,
+                ) || otx_configs.dynamic_outputs
+                    && lock_matcher.is_included_in_outputs(
+                        (state.output_end + fixed_output_cells) as usize,
+                        output_end as usize,
+                    ));
+
+            if !lock_hash_existing_in_fixed && !lock_hash_existing_in_dynamic {
+                state.input_end = input_end;
+                state.output_end = output_end;
+                state.cell_dep_end = cell_dep_end;
+                state.header_dep_end = header_dep_end;
+                continue;
+            }
+            matched_witness_indices.push(witness_index);
+
+            if lock_hash_existing_in_fixed {
+                let invoked = handler.handle(
+                    otx,
+                    OtxSigningRange::default()
+                        .with_inputs(state.input_end, fixed_input_cells)
+                        .with_outputs(state.output_end, fixed_output_cells)
+                        .with_cell_deps(state.cell_dep_end, fixed_cell_deps)
+                        .with_header_deps(state.header_dep_end, fixed_header_deps),
+                    &otx_context,
+                )?;
+                execution_count += invoked as usize;
+            }
+
+            if lock_hash_existing_in_dynamic {
+                let invoked = handler.handle(
+                    otx,
+                    OtxSigningRange::default()
+                        .with_inputs(state.input_end, fixed_input_cells + dynamic_input_cells)
+                        .with_outputs(state.output_end, fixed_output_cells + dynamic_output_cells)
+                        .with_cell_deps(state.cell_dep_end, fixed_cell_deps + dynamic_cell_deps)
+                        .with_header_deps(
+                            state.header_dep_end,
+                            fixed_header_deps + dynamic_header_deps,
+                        ),
+                    &OtxContext {
+                        dynamic: true,
+                        ..otx_context
+                    },
+                )?;
+                execution_count += invoked as usize;
+            }
+
+            if lock_matcher.strictness == OtxStrictness::Strict {
+                let expected =
+                    lock_hash_existing_in_fixed as usize + lock_hash_existing_in_dynamic as usize;
+                let found = count_seals(&otx.seals()?, &lock_matcher.script_hash)?;
+                if found != expected {
+                    log!(
+                        "OTX at index {} has {} seals for the current script, {} expected",
+                        witness_index,
+                        found,
+                        expected
+                    );
+                    return Err(Error::WrongOtx);
+                }
+            }
+
+            // step 6.h
+            state.input_end = input_end;
+            state.output_end = output_end;
+            state.cell_dep_end = cell_dep_end;
+            state.header_dep_end = header_dep_end;
+        } // end of step 6 loop
+
+        log!(
+            "the first non OTX witness is at index {}",
+            first_non_otx_witness_index
+        );
+        otx_input_ranges.push((state.input_start, state.input_end));
+        otx_witness_ranges.push((otx_start_index, first_non_otx_witness_index));
+        previous_state = Some(state);
     }
-    if found {
-        execution_count += 1;
-        log!("extra callback is invoked");
-        cobuild_normal_entry(verifier, &script_hashes_cache)?;
+
+    // step 7
+    for (index, witness) in witness_layouts.iter().enumerate() {
+        // OTX witnesses are only allowed in (i, j) of some segment
+        if witness.as_otx().is_some()
+            && !otx_witness_ranges
+                .iter()
+                .any(|(i, j)| index > *i && index < *j)
+        {
+            log!(
+                "WrongWitnessLayout at index = {} (otx_count = {})",
+                index,
+                otx_count
+            );
+            return Err(Error::WrongWitnessLayout);
+        }
     }
-    log!("execution_count = {}", execution_count);
-    Ok(true)
+
+    Ok(OtxVerification {
+        input_ranges: otx_input_ranges,
+        execution_count,
+        matched_witness_indices,
+        witness_end: otx_witness_ranges.last().map_or(0, |(_, j)| *j),
+    })
 }
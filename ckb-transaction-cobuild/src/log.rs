@@ -1,3 +1,17 @@
+use core::fmt;
+
+/// Formats bytes as lowercase hex in `log!` messages, e.g. script hashes.
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
 #[macro_export]
 macro_rules! log {
     ($fmt:literal) => {
@@ -11,3 +25,15 @@ macro_rules! log {
         let _ = &($($args),+);
     };
 }
+
+/// Same as `log!`, but only enabled by the `hash-debug` feature. It's used to
+/// log every component fed to a signing message hasher.
+#[macro_export]
+macro_rules! hash_debug {
+    ($fmt:literal, $($args:expr),+) => {
+        #[cfg(feature = "hash-debug")]
+        $crate::log!($fmt, $($args),+);
+        #[cfg(not(feature = "hash-debug"))]
+        let _ = &($($args),+);
+    };
+}
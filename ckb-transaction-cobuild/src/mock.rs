@@ -0,0 +1,275 @@
+//! In-memory syscalls, enabled by the `mock-syscalls` feature.
+//!
+//! Every function of this crate reading the transaction via syscalls, e.g.
+//! `cobuild_entry` or `generate_signing_message_hash`, reads the transaction
+//! set by `set_mock_transaction` instead, so it can be unit-tested on the host
+//! without a VM. The mocked transaction is per thread, so tests can run in
+//! parallel.
+//!
+//! ```ignore
+//! set_mock_transaction(MockTransaction::new(tx, resolved_inputs, lock_script));
+//! let result = cobuild_entry_detailed(&mut verifier)?;
+//! ```
+//!
+//! The current script runs as a lock by default, so its script group is made
+//! of the inputs using it as lock. Run as a type, see
+//! `MockTransaction::with_script_group_type`, its group is made of the inputs
+//! and outputs using it as type. Header deps can't be loaded.
+extern crate std;
+
+use alloc::vec::Vec;
+use ckb_hash::blake2b_256;
+use ckb_std::{ckb_constants::Source, error::SysError};
+use core::{
+    cell::{Cell, RefCell},
+    cmp::min,
+};
+use molecule::prelude::*;
+
+use crate::{
+    lazy_reader::SharedTransactionSlot,
+    schemas::{
+        basic::ResolvedInputs,
+        blockchain::{CellOutput, Script, Transaction, WitnessArgs},
+    },
+};
+
+/// Whether the current script runs as the lock or the type of its cells.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptGroupType {
+    #[default]
+    Lock,
+    Type,
+}
+
+/// The transaction, and the cells it references, read by the mocked syscalls.
+#[derive(Clone)]
+pub struct MockTransaction {
+    pub tx: Transaction,
+    /// the input cells and their data, indexed like the inputs of `tx`
+    pub resolved_inputs: ResolvedInputs,
+    /// the cell dep cells and their data, indexed like the cell deps of `tx`
+    pub resolved_cell_deps: ResolvedInputs,
+    /// the script being run
+    pub script: Script,
+    /// the kind of the script group being run
+    pub script_group_type: ScriptGroupType,
+}
+
+impl MockTransaction {
+    /// Creates a transaction without resolved cell deps, running `script` as
+    /// a lock.
+    pub fn new(tx: Transaction, resolved_inputs: ResolvedInputs, script: Script) -> Self {
+        Self {
+            tx,
+            resolved_inputs,
+            resolved_cell_deps: ResolvedInputs::default(),
+            script,
+            script_group_type: ScriptGroupType::Lock,
+        }
+    }
+
+    pub fn with_script_group_type(mut self, script_group_type: ScriptGroupType) -> Self {
+        self.script_group_type = script_group_type;
+        self
+    }
+
+    pub fn with_resolved_cell_deps(mut self, resolved_cell_deps: ResolvedInputs) -> Self {
+        self.resolved_cell_deps = resolved_cell_deps;
+        self
+    }
+
+    /// Maps a group source to the transaction index, and returns the cell and
+    /// its data.
+    fn cell(&self, index: usize, source: Source) -> Result<(CellOutput, Vec<u8>), SysError> {
+        let (index, source) = self.resolve(index, source)?;
+        let (cells, data) = match source {
+            Source::Input => (
+                self.resolved_inputs.outputs(),
+                self.resolved_inputs.outputs_data(),
+            ),
+            Source::Output => (self.tx.raw().outputs(), self.tx.raw().outputs_data()),
+            Source::CellDep => (
+                self.resolved_cell_deps.outputs(),
+                self.resolved_cell_deps.outputs_data(),
+            ),
+            _ => return Err(SysError::IndexOutOfBound),
+        };
+        let cell = cells.get(index).ok_or(SysError::IndexOutOfBound)?;
+        let data = data.get(index).ok_or(SysError::IndexOutOfBound)?;
+        Ok((cell, data.raw_data().to_vec()))
+    }
+
+    fn resolve(&self, index: usize, source: Source) -> Result<(usize, Source), SysError> {
+        let script_hash = blake2b_256(self.script.as_slice());
+        // the lock or the type of a cell, according to the group type
+        let group_script = |cell: CellOutput| match self.script_group_type {
+            ScriptGroupType::Lock => Some(cell.lock()),
+            ScriptGroupType::Type => cell.type_().to_opt(),
+        };
+        let uses_script = |cell: CellOutput| {
+            group_script(cell).is_some_and(|script| blake2b_256(script.as_slice()) == script_hash)
+        };
+        let (in_group, source): (Vec<bool>, _) = match source {
+            Source::GroupInput => (
+                self.resolved_inputs
+                    .outputs()
+                    .into_iter()
+                    .map(uses_script)
+                    .collect(),
+                Source::Input,
+            ),
+            // a lock script group has no outputs
+            Source::GroupOutput => (
+                self.tx
+                    .raw()
+                    .outputs()
+                    .into_iter()
+                    .map(|cell| {
+                        self.script_group_type == ScriptGroupType::Type && uses_script(cell)
+                    })
+                    .collect(),
+                Source::Output,
+            ),
+            _ => return Ok((index, source)),
+        };
+        let index = in_group
+            .iter()
+            .enumerate()
+            .filter(|(_, in_group)| **in_group)
+            .nth(index)
+            .map(|(index, _)| index)
+            .ok_or(SysError::IndexOutOfBound)?;
+        Ok((index, source))
+    }
+}
+
+std::thread_local! {
+    static MOCK_TRANSACTION: RefCell<Option<MockTransaction>> = const { RefCell::new(None) };
+    static CELL_LOAD_COUNT: Cell<usize> = const { Cell::new(0) };
+    static SHARED_TRANSACTION: SharedTransactionSlot = const { RefCell::new(None) };
+}
+
+/// Sets the transaction read by the mocked syscalls of the current thread, and
+/// resets `cell_load_count` and the transaction of `shared_transaction`.
+pub fn set_mock_transaction(tx: MockTransaction) {
+    MOCK_TRANSACTION.with(|mock| *mock.borrow_mut() = Some(tx));
+    CELL_LOAD_COUNT.with(|count| count.set(0));
+    SHARED_TRANSACTION.with(|shared| *shared.borrow_mut() = None);
+}
+
+/// Number of `load_cell` and `load_cell_data` calls since the transaction was
+/// set. Each call is a syscall in the VM, so it measures what caching saves.
+pub fn cell_load_count() -> usize {
+    CELL_LOAD_COUNT.with(|count| count.get())
+}
+
+pub(crate) fn with_shared_transaction_slot<T, F: FnOnce(&SharedTransactionSlot) -> T>(f: F) -> T {
+    SHARED_TRANSACTION.with(f)
+}
+
+fn with_mock<T, F: FnOnce(&MockTransaction) -> T>(f: F) -> T {
+    MOCK_TRANSACTION.with(|mock| {
+        f(mock
+            .borrow()
+            .as_ref()
+            .expect("no mock transaction set, see set_mock_transaction"))
+    })
+}
+
+/// Copies `data` from `offset` into `buf`, with the return value of a partial
+/// loading syscall.
+fn load_data(data: &[u8], buf: &mut [u8], offset: usize) -> Result<usize, SysError> {
+    let data = data.get(offset..).unwrap_or_default();
+    let len = min(buf.len(), data.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    if data.len() > buf.len() {
+        Err(SysError::LengthNotEnough(data.len()))
+    } else {
+        Ok(data.len())
+    }
+}
+
+pub fn load_transaction(buf: &mut [u8], offset: usize) -> Result<usize, SysError> {
+    with_mock(|mock| load_data(mock.tx.as_slice(), buf, offset))
+}
+
+pub fn load_cell(
+    buf: &mut [u8],
+    offset: usize,
+    index: usize,
+    source: Source,
+) -> Result<usize, SysError> {
+    CELL_LOAD_COUNT.with(|count| count.set(count.get() + 1));
+    let (cell, _) = with_mock(|mock| mock.cell(index, source))?;
+    load_data(cell.as_slice(), buf, offset)
+}
+
+pub fn load_cell_data(
+    buf: &mut [u8],
+    offset: usize,
+    index: usize,
+    source: Source,
+) -> Result<usize, SysError> {
+    CELL_LOAD_COUNT.with(|count| count.set(count.get() + 1));
+    let (_, data) = with_mock(|mock| mock.cell(index, source))?;
+    load_data(&data, buf, offset)
+}
+
+/// Input and output sources index the witnesses directly, group sources are
+/// mapped to the index of the cell in the transaction.
+pub fn load_witness(
+    buf: &mut [u8],
+    offset: usize,
+    index: usize,
+    source: Source,
+) -> Result<usize, SysError> {
+    with_mock(|mock| {
+        let (index, _) = mock.resolve(index, source)?;
+        let witness = mock
+            .tx
+            .witnesses()
+            .get(index)
+            .ok_or(SysError::IndexOutOfBound)?;
+        load_data(&witness.raw_data(), buf, offset)
+    })
+}
+
+pub fn load_tx_hash() -> Result<[u8; 32], SysError> {
+    with_mock(|mock| Ok(blake2b_256(mock.tx.raw().as_slice())))
+}
+
+pub fn load_script_hash() -> Result<[u8; 32], SysError> {
+    with_mock(|mock| Ok(blake2b_256(mock.script.as_slice())))
+}
+
+pub fn load_script() -> Result<Script, SysError> {
+    with_mock(|mock| Ok(mock.script.clone()))
+}
+
+pub fn load_cell_lock(index: usize, source: Source) -> Result<Script, SysError> {
+    with_mock(|mock| Ok(mock.cell(index, source)?.0.lock()))
+}
+
+pub fn load_cell_type(index: usize, source: Source) -> Result<Option<Script>, SysError> {
+    with_mock(|mock| Ok(mock.cell(index, source)?.0.type_().to_opt()))
+}
+
+pub fn load_cell_lock_hash(index: usize, source: Source) -> Result<[u8; 32], SysError> {
+    load_cell_lock(index, source).map(|script| blake2b_256(script.as_slice()))
+}
+
+pub fn load_cell_type_hash(index: usize, source: Source) -> Result<Option<[u8; 32]>, SysError> {
+    load_cell_type(index, source).map(|script| script.map(|script| blake2b_256(script.as_slice())))
+}
+
+pub fn load_witness_args(index: usize, source: Source) -> Result<WitnessArgs, SysError> {
+    let mut buf = Vec::new();
+    let len = match load_witness(&mut buf, 0, index, source) {
+        Ok(len) | Err(SysError::LengthNotEnough(len)) => len,
+        Err(err) => return Err(err),
+    };
+    buf.resize(len, 0);
+    load_witness(&mut buf, 0, index, source)?;
+    WitnessArgs::from_slice(&buf).map_err(|_| SysError::Encoding)
+}
@@ -1,17 +1,24 @@
+use alloc::vec::Vec;
 use ckb_std::ckb_constants::Source;
-use molecule::lazy_reader::Cursor;
+use core::ops::Range;
 
 use crate::{
     blake2b::new_otx_blake2b,
-    error::Error,
-    lazy_reader::{self, new_input_cell_data},
-    log,
+    error::{Error, ResultExt},
+    hash_debug, input_lock_outside_otxs,
+    lazy_reader::{CachedTransaction, ResolvedCells},
+    log, parse_witness_layouts,
     schemas2::{
         basic::{self, Message},
         blockchain, top_level,
     },
+    syscalls::load_script_hash,
+    utils::{cache_script_hashes_with_cell_deps, ct_eq_32, WitnessLayoutExt},
+    verify_otx_segments, Callback, CobuildLimits, FnCallback, IgnoreMessage, LockMatcher,
+    OtxRangeHandler, OtxSealVerifier,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct OtxDynamicConfigs {
     pub dynamic_inputs: bool,
     pub dynamic_outputs: bool,
@@ -41,6 +48,26 @@ impl TryFrom<u8> for OtxDynamicConfigs {
     }
 }
 
+impl OtxDynamicConfigs {
+    /// Returns the `flag` byte of an `Otx` with these configs. It's the
+    /// inverse of `try_from`.
+    pub fn to_flag(&self) -> u8 {
+        (self.dynamic_inputs as u8)
+            | (self.dynamic_outputs as u8) << 1
+            | (self.dynamic_cell_deps as u8) << 2
+            | (self.dynamic_header_deps as u8) << 3
+    }
+
+    /// Returns whether any dynamic section is declared.
+    pub fn any_dynamic(&self) -> bool {
+        self.dynamic_inputs
+            || self.dynamic_outputs
+            || self.dynamic_cell_deps
+            || self.dynamic_header_deps
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct OtxSigningRange {
     pub input_start: u32,
     pub inputs_count: u32,
@@ -50,70 +77,331 @@ pub struct OtxSigningRange {
     pub cell_deps_count: u32,
     pub header_dep_start: u32,
     pub header_deps_count: u32,
+    /// Also hash the data of every cell dep, so the signature commits to the
+    /// code or data of the deps rather than only to their out points. It is
+    /// off by default and not used by `cobuild_entry`: signers and the lock
+    /// script calling `generate_otx_smh` must agree on it.
+    pub hash_cell_dep_data: bool,
+    /// Fields of the output cells left out of the hash. Like
+    /// `hash_cell_dep_data`, it's not used by `cobuild_entry`.
+    pub output_hash_mask: OutputHashMask,
+}
+
+/// Fields of the output cells of an `OtxSigningRange` left out of the signing
+/// message hash. Nothing is masked by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OutputHashMask {
+    /// Hash the `lock` and `type` of each output cell instead of the whole
+    /// `CellOutput`, so the signature doesn't cover the capacity. Anyone can
+    /// then change the capacities of the outputs, e.g. to pay the fee, and
+    /// the outputs of the signer can be drained down to their minimal
+    /// occupied capacity. Only use it for outputs whose capacity the signer
+    /// doesn't care about.
+    pub exclude_capacity: bool,
+}
+
+impl OtxSigningRange {
+    pub fn with_inputs(mut self, start: u32, count: u32) -> Self {
+        self.input_start = start;
+        self.inputs_count = count;
+        self
+    }
+
+    pub fn with_outputs(mut self, start: u32, count: u32) -> Self {
+        self.output_start = start;
+        self.outputs_count = count;
+        self
+    }
+
+    pub fn with_cell_deps(mut self, start: u32, count: u32) -> Self {
+        self.cell_dep_start = start;
+        self.cell_deps_count = count;
+        self
+    }
+
+    pub fn with_header_deps(mut self, start: u32, count: u32) -> Self {
+        self.header_dep_start = start;
+        self.header_deps_count = count;
+        self
+    }
+
+    pub fn with_cell_dep_data(mut self, enabled: bool) -> Self {
+        self.hash_cell_dep_data = enabled;
+        self
+    }
+
+    pub fn with_output_hash_mask(mut self, mask: OutputHashMask) -> Self {
+        self.output_hash_mask = mask;
+        self
+    }
+}
+
+/// Returns the indices `start..start + count`, or `Error::WrongCount` when the
+/// end overflows.
+pub(crate) fn index_range(start: u32, count: u32) -> Result<Range<usize>, Error> {
+    let end = start.checked_add(count).ok_or(Error::WrongCount)?;
+    Ok(start as usize..end as usize)
+}
+
+/// Checks that the OTXs of a segment consume a coherent range of cells and
+/// deps, and returns where the range ends. Every array is indexed by kind:
+/// inputs, outputs, cell deps and header deps.
+///
+/// The segment starts at `starts`, the indices of its `OtxStart`, and each of
+/// `group_counts` holds the fixed plus dynamic counts of one OTX. The range is
+/// coherent when, for every kind, `start + sum(counts)` doesn't overflow and
+/// doesn't exceed the length in `tx_lens`. Otherwise `Error::WrongCount` is
+/// returned. Cells and deps left after the end are not an error: the inputs
+/// there are verified by the normal entry.
+///
+/// `cobuild_entry` runs it on every OTX as it's read, with the ends of the
+/// previous OTX as `starts`, so an OTX overrunning the transaction is
+/// rejected before any of its seals is verified.
+pub fn validate_otx_totals(
+    starts: [u32; 4],
+    group_counts: &[[u32; 4]],
+    tx_lens: [usize; 4],
+) -> Result<[u32; 4], Error> {
+    let mut ends = starts;
+    for counts in group_counts {
+        for (end, count) in ends.iter_mut().zip(counts) {
+            *end = end.checked_add(*count).ok_or(Error::WrongCount)?;
+        }
+    }
+    for (end, len) in ends.iter().zip(tx_lens) {
+        if *end as usize > len {
+            log!("OTX segment ends at {} beyond the length {}", end, len);
+            return Err(Error::WrongCount);
+        }
+    }
+    Ok(ends)
+}
+
+/// Context of the OTX group passed to `Callback::invoke_otx`.
+#[derive(Debug, Clone, Copy)]
+pub struct OtxContext {
+    /// witness index of the `Otx`
+    pub witness_index: usize,
+    pub flag: u8,
+    pub fixed_input_cells: u32,
+    pub fixed_output_cells: u32,
+    pub fixed_cell_deps: u32,
+    pub fixed_header_deps: u32,
+    pub dynamic_input_cells: u32,
+    pub dynamic_output_cells: u32,
+    pub dynamic_cell_deps: u32,
+    pub dynamic_header_deps: u32,
+    /// absolute index of the first input cell of the group
+    pub input_start: u32,
+    /// absolute index of the first output cell of the group
+    pub output_start: u32,
+    /// absolute index of the first cell dep of the group
+    pub cell_dep_start: u32,
+    /// absolute index of the first header dep of the group
+    pub header_dep_start: u32,
+    /// whether the signing message hash covers the dynamic part, which means
+    /// the current script is found in the dynamic inputs, or in the dynamic
+    /// outputs as lock or type
+    pub dynamic: bool,
+}
+
+/// Finds the seal for `script_hash` in `seals`. The first matching seal wins
+/// and duplicated seals are ignored.
+///
+/// The iteration order is meaningful: when a script appears in both the fixed
+/// and the dynamic part of an OTX, it needs two different seals. The seal for
+/// the fixed part is searched from the front (`reverse` is false) and the seal
+/// for the dynamic part from the back (`reverse` is true).
+///
+/// Returns `Error::NoSeals` if `seals` is empty, e.g. the signer forgot to
+/// attach any seal, and `Error::NoSealFound` if no seal is for `script_hash`.
+pub fn find_seal(
+    seals: &basic::SealPairVec,
+    script_hash: &[u8; 32],
+    reverse: bool,
+) -> Result<Vec<u8>, Error> {
+    let len = seals.len()?;
+    if len == 0 {
+        log!("no seals in OTX");
+        return Err(Error::NoSeals);
+    }
+    for i in 0..len {
+        let index = if reverse { len - 1 - i } else { i };
+        let seal_pair = seals.get(index)?;
+        if ct_eq_32(&seal_pair.script_hash()?, script_hash) {
+            return Ok(seal_pair.seal()?.try_into()?);
+        }
+    }
+    log!("seal can't be found");
+    Err(Error::NoSealFound)
+}
+
+/// Counts the seals for `script_hash` in `seals`.
+pub fn count_seals(seals: &basic::SealPairVec, script_hash: &[u8; 32]) -> Result<usize, Error> {
+    let mut count = 0;
+    for index in 0..seals.len()? {
+        if ct_eq_32(&seals.get(index)?.script_hash()?, script_hash) {
+            count += 1;
+        }
+    }
+    Ok(count)
 }
 
 /// generate OTX signing message hash
+///
+/// The hasher uses the `ckb-tcob-otxhash` personalization and is fed with, in
+/// order:
+///
+/// 1. the molecule encoded `Message`
+/// 2. `inputs_count` as u32 little endian, then for each input: the
+///    `CellInput`, the `CellOutput` of the input cell, the data length as u32
+///    little endian and the data
+/// 3. `outputs_count` as u32 little endian, then for each output: the
+///    `CellOutput`, the data length as u32 little endian and the data. With
+///    `output_hash_mask.exclude_capacity`, the `CellOutput` is replaced by its
+///    `lock`, then a byte 1 followed by its `type`, or a byte 0 when absent
+/// 4. `cell_deps_count` as u32 little endian, then each `CellDep` (37 bytes).
+///    With `hash_cell_dep_data`, each `CellDep` is followed by the data length
+///    of the dep cell as u32 little endian and the data
+/// 5. `header_deps_count` as u32 little endian, then each header dep hash (32
+///    bytes)
+///
+/// An empty `Message` (no actions) is still hashed as its 12 byte molecule
+/// encoding, so OTXs without actions are bound to their signing range just
+/// like the others.
+///
+/// Cell deps and header deps are fixed size, so they are not length
+/// prefixed. Changing this format invalidates all existing OTX signatures.
+/// `hash_cell_dep_data` produces a different hash for the same range, which
+/// only verifies against signatures made with the same setting, and so does
+/// `output_hash_mask`.
 pub fn generate_otx_smh(
     raw_tx: &blockchain::RawTransaction,
     message: Message,
     signing_range: OtxSigningRange,
+) -> Result<[u8; 32], Error> {
+    generate_otx_smh_cached(
+        &CachedTransaction::without_cache(),
+        raw_tx,
+        message,
+        signing_range,
+    )
+}
+
+/// Same as `generate_otx_smh`, but the input cells are read through
+/// `cached_tx`, so they can be shared with other signing message hashes.
+pub fn generate_otx_smh_cached(
+    cached_tx: &CachedTransaction,
+    raw_tx: &blockchain::RawTransaction,
+    message: Message,
+    signing_range: OtxSigningRange,
+) -> Result<[u8; 32], Error> {
+    otx_signing_message_hash_for(raw_tx, message, signing_range, cached_tx)
+}
+
+/// Same as `generate_otx_smh`, but the input cells, and the cell dep data with
+/// `hash_cell_dep_data`, are provided by `resolved_inputs` instead of being
+/// loaded via syscalls, e.g. by a `basic::ResolvedInputs`. It can run in a
+/// host context or against a mocked transaction.
+pub fn otx_signing_message_hash_for<R: ResolvedCells>(
+    raw_tx: &blockchain::RawTransaction,
+    message: Message,
+    signing_range: OtxSigningRange,
+    resolved_inputs: &R,
 ) -> Result<[u8; 32], Error> {
     let mut hasher = new_otx_blake2b();
-    hasher.update_cursor(message.cursor.clone());
+    hash_debug!("hash-debug message len={}", message.cursor.size);
+    hasher.update_cursor(message.cursor.clone())?;
 
+    hash_debug!("hash-debug inputs_count {}", signing_range.inputs_count);
     hasher.update(&signing_range.inputs_count.to_le_bytes());
     let inputs = raw_tx.inputs()?;
-    for index in signing_range.input_start as usize
-        ..(signing_range.input_start + signing_range.inputs_count) as usize
-    {
+    for index in index_range(signing_range.input_start, signing_range.inputs_count)? {
         // input
-        hasher.update_cursor(inputs.get(index)?.cursor);
+        hash_debug!("hash-debug input {}", index);
+        hasher.update_cursor(inputs.get(index).at_cell(index, Source::Input)?.cursor)?;
 
-        let reader = lazy_reader::InputCellReader::try_new(index, Source::Input)?;
-        let cursor: Cursor = reader.into();
-        let data_cursor = new_input_cell_data(index, Source::Input)?;
+        let cursor = resolved_inputs
+            .resolved_cell(index)
+            .at_cell(index, Source::Input)?;
+        let data_cursor = resolved_inputs
+            .resolved_cell_data(index)
+            .at_cell(index, Source::Input)?;
+        hash_debug!(
+            "hash-debug input_cell {} len={} data_len={}",
+            index,
+            cursor.size,
+            data_cursor.size
+        );
         // input cell
-        hasher.update_cursor(cursor);
-        // input cell data size
-        hasher.update(&(data_cursor.size as u32).to_le_bytes());
-        // input cell data
-        hasher.update_cursor(data_cursor);
+        hasher.update_cursor(cursor)?;
+        // input cell data size and data
+        hasher.update_cursor_with_len(data_cursor)?;
     }
 
+    hash_debug!("hash-debug outputs_count {}", signing_range.outputs_count);
     hasher.update(&signing_range.outputs_count.to_le_bytes());
-    for index in signing_range.output_start as usize
-        ..(signing_range.output_start + signing_range.outputs_count) as usize
-    {
+    for index in index_range(signing_range.output_start, signing_range.outputs_count)? {
         let outputs = raw_tx.outputs()?;
         let outputs_data = raw_tx.outputs_data()?;
-        // output cell
-        hasher.update_cursor(outputs.get(index)?.cursor);
+        let output = outputs.get(index)?;
+        if signing_range.output_hash_mask.exclude_capacity {
+            // output cell without capacity
+            hasher.update_cursor(output.lock()?.cursor)?;
+            match output.type_()? {
+                Some(type_script) => {
+                    hasher.update(&[1]);
+                    hasher.update_cursor(type_script.cursor)?;
+                }
+                None => hasher.update(&[0]),
+            }
+        } else {
+            // output cell
+            hasher.update_cursor(output.cursor)?;
+        }
         let data = outputs_data.get(index)?;
-        // output cell data size
-        hasher.update(&(data.size as u32).to_le_bytes());
-        // output cell data
-        hasher.update_cursor(data);
+        hash_debug!("hash-debug output_cell_data {} len={}", index, data.size);
+        // output cell data size and data
+        hasher.update_cursor_with_len(data)?;
     }
 
+    hash_debug!(
+        "hash-debug cell_deps_count {}",
+        signing_range.cell_deps_count
+    );
     hasher.update(&signing_range.cell_deps_count.to_le_bytes());
-    for index in signing_range.cell_dep_start as usize
-        ..(signing_range.cell_dep_start + signing_range.cell_deps_count) as usize
-    {
+    for index in index_range(signing_range.cell_dep_start, signing_range.cell_deps_count)? {
         let cell_deps = raw_tx.cell_deps()?;
-        hasher.update_cursor(cell_deps.get(index)?.cursor)
+        hasher.update_cursor(cell_deps.get(index)?.cursor)?;
+        if signing_range.hash_cell_dep_data {
+            let data_cursor = resolved_inputs
+                .resolved_cell_dep_data(index)
+                .at_cell(index, Source::CellDep)?;
+            hash_debug!(
+                "hash-debug cell_dep_data {} len={}",
+                index,
+                data_cursor.size
+            );
+            // cell dep data size and data
+            hasher.update_cursor_with_len(data_cursor)?;
+        }
     }
 
+    hash_debug!(
+        "hash-debug header_deps_count {}",
+        signing_range.header_deps_count
+    );
     hasher.update(&signing_range.header_deps_count.to_le_bytes());
-    for index in signing_range.header_dep_start as usize
-        ..(signing_range.header_dep_start + signing_range.header_deps_count) as usize
-    {
+    for index in index_range(
+        signing_range.header_dep_start,
+        signing_range.header_deps_count,
+    )? {
         let header_deps = raw_tx.header_deps()?;
         hasher.update(&header_deps.get(index)?);
     }
 
     let mut result = [0u8; 32];
-    let count = hasher.count();
-    hasher.finalize(&mut result);
+    let count = hasher.finalize_with_count(&mut result);
     log!(
         "generate_otx_smh totally hashed {} bytes and hash is {:?}",
         count,
@@ -123,52 +411,285 @@ pub fn generate_otx_smh(
 }
 
 ///
-/// parse all witnesses and find out the `OtxStart`
+/// parse all witnesses and find out every `OtxStart`, with its witness index.
+///
+/// A transaction may contain several OTX segments, each starting with an
+/// `OtxStart` followed by one or more contiguous `Otx` witnesses. Segments
+/// can be separated by non-OTX witnesses. Returns
+/// - `Error::DuplicateOtxStart` if an `OtxStart` directly follows another one
+/// - `Error::WrongOtxStart` if an `OtxStart` isn't followed by any `Otx`
+/// - `Error::OtxBeforeStart` if an `Otx` appears before any `OtxStart`
+/// - `Error::WrongWitnessLayout` if an `Otx` is separated from its segment by
+///   a `SighashAll` or `SighashAllOnly`
+/// - `Error::OtxNotContiguous` if an `Otx` is separated from its segment by
+///   other witnesses
 ///
 pub fn fetch_otx_start(
     witnesses: &[Option<top_level::WitnessLayout>],
-) -> Result<(Option<basic::OtxStart>, usize), Error> {
-    let mut otx_start = None;
-    let mut start_index = 0;
-    let mut end_index = 0;
+) -> Result<Vec<(basic::OtxStart, usize)>, Error> {
+    let mut otx_starts: Vec<(basic::OtxStart, usize)> = Vec::new();
+    // index of the last OtxStart or Otx witness in current segment
+    let mut end_index = None;
 
     for (i, witness) in witnesses.iter().enumerate() {
-        if let Some(witness_layout) = witness {
-            match witness_layout {
-                top_level::WitnessLayout::OtxStart(start) => {
-                    if otx_start.is_none() {
-                        otx_start = Some(start.clone());
-                        start_index = i;
-                        end_index = i;
-                    } else {
-                        log!("Duplicated OtxStart found");
-                        return Err(Error::WrongWitnessLayout);
-                    }
+        if let Some(start) = witness.as_otx_start() {
+            if let Some((_, start_index)) = otx_starts.last() {
+                if *start_index + 1 == i {
+                    log!(
+                        "OtxStart at index {} is followed by another OtxStart",
+                        start_index
+                    );
+                    return Err(Error::DuplicateOtxStart);
                 }
-                top_level::WitnessLayout::Otx(_) => {
-                    if otx_start.is_none() {
-                        log!("A Otx without OtxStart found");
-                        return Err(Error::WrongWitnessLayout);
-                    } else if end_index + 1 != i {
-                        log!("Otx are not continuous");
+                if end_index == Some(*start_index) {
+                    log!("OtxStart at index {} is not followed by Otx", start_index);
+                    return Err(Error::WrongOtxStart);
+                }
+            }
+            otx_starts.push((start.clone(), i));
+            end_index = Some(i);
+        } else if witness.as_otx().is_some() {
+            match end_index {
+                None => {
+                    log!("Otx at index {} has no OtxStart before it", i);
+                    return Err(Error::OtxBeforeStart);
+                }
+                Some(end) if end + 1 != i => {
+                    // a SighashAll ending the run early would otherwise look
+                    // like an ordinary separator
+                    if let Some(index) = (end + 1..i).find(|&index| {
+                        witnesses[index].as_sighash_all().is_some()
+                            || witnesses[index].as_sighash_all_only().is_some()
+                    }) {
+                        log!(
+                            "SighashAll or SighashAllOnly at index {} inside the OTX run ending at index {}",
+                            index,
+                            i
+                        );
                         return Err(Error::WrongWitnessLayout);
-                    } else {
-                        end_index = i;
                     }
+                    log!("Otx at index {} is not contiguous with index {}", i, end);
+                    return Err(Error::OtxNotContiguous);
                 }
-                _ => {}
+                _ => end_index = Some(i),
             }
         }
     }
 
-    if otx_start.is_some() {
-        if end_index > 0 {
-            Ok((otx_start, start_index))
-        } else {
-            log!("end_index == 0, there is no OTX");
-            Err(Error::WrongOtxStart)
+    if let Some((_, start_index)) = otx_starts.last() {
+        if end_index == Some(*start_index) {
+            log!("OtxStart at index {} is not followed by Otx", start_index);
+            return Err(Error::WrongOtxStart);
         }
-    } else {
-        Ok((None, 0))
     }
+    Ok(otx_starts)
+}
+
+/// Verifies the OTXs of the current transaction for `current_script_hash`.
+///
+/// `cobuild_entry` runs the full flow of the current lock script: SighashAll
+/// or SighashAllOnly when there is no OTX, every OTX containing the script,
+/// and SighashAll again for its inputs outside of all OTXs. This function
+/// only runs the OTX part with the same routine, for the script hash given
+/// by the caller: every OTX group containing `current_script_hash` as an
+/// input lock is verified by `verifier`, and inputs outside of OTXs are left
+/// unchecked. A type script can use it to check the OTX seals of a given lock
+/// script, but it has to make sure the remaining inputs are verified some other
+/// way.
+///
+/// Returns whether `verifier` has been invoked at least once.
+pub fn verify_otx_message<F: Callback>(
+    current_script_hash: [u8; 32],
+    verifier: F,
+) -> Result<bool, Error> {
+    Ok(verify_otx_message_indexed(current_script_hash, verifier)?.is_some())
+}
+
+/// Same as `verify_otx_message`, but returns the witness index of the first
+/// OTX containing `current_script_hash`, or `None` if the script isn't in any
+/// OTX. Errors are the same: e.g. an invalid seal is still returned as the
+/// verifier's error, not as `None`.
+pub fn verify_otx_message_indexed<F: Callback>(
+    current_script_hash: [u8; 32],
+    verifier: F,
+) -> Result<Option<usize>, Error> {
+    let cached_tx = CachedTransaction::new();
+    let tx = cached_tx.transaction();
+    let (witness_layouts, activated) = parse_witness_layouts(tx)?;
+    if !activated {
+        return Ok(None);
+    }
+    let otx_starts = fetch_otx_start(&witness_layouts)?;
+    if otx_starts.is_empty() {
+        return Ok(None);
+    }
+    let script_hashes_cache = cache_script_hashes_with_cell_deps();
+    let otx_verification = verify_otx_segments(
+        &mut OtxSealVerifier::new(
+            &mut IgnoreMessage(verifier),
+            &cached_tx,
+            current_script_hash,
+        ),
+        &cached_tx,
+        &witness_layouts,
+        otx_starts,
+        &script_hashes_cache,
+        &LockMatcher::by_script_hash(current_script_hash, &script_hashes_cache),
+        &CobuildLimits::default(),
+    )?;
+    Ok(otx_verification.matched_witness_indices.first().copied())
+}
+
+/// Verifies the seals of the current script in a single OTX, the one at
+/// `group_index` among all `Otx` witnesses of the transaction, counted from 0
+/// in witness order across OTX segments. The whole transaction is walked like
+/// in `verify_otx_message`, so the start offsets of the OTX are derived from
+/// the OTXs before it and every OTX is checked, but `verifier` is only
+/// invoked for this one.
+///
+/// Returns `Error::OtxGroupNotFound` if there are at most `group_index`
+/// OTXs, and `Error::NoSealFound` if the current script locks no input of the
+/// OTX.
+pub fn verify_otx_group<F: Callback>(verifier: F, group_index: usize) -> Result<(), Error> {
+    let cached_tx = CachedTransaction::new();
+    let (witness_layouts, _) = parse_witness_layouts(cached_tx.transaction())?;
+    let witness_index = witness_layouts
+        .iter()
+        .enumerate()
+        .filter(|(_, witness_layout)| witness_layout.as_otx().is_some())
+        .nth(group_index)
+        .map(|(witness_index, _)| witness_index)
+        .ok_or(Error::OtxGroupNotFound)?;
+    let otx_starts = fetch_otx_start(&witness_layouts)?;
+
+    let current_script_hash = load_script_hash()?;
+    let script_hashes_cache = cache_script_hashes_with_cell_deps();
+    let otx_verification = verify_otx_segments(
+        &mut OnlyWitness {
+            handler: OtxSealVerifier::new(
+                &mut IgnoreMessage(verifier),
+                &cached_tx,
+                current_script_hash,
+            ),
+            witness_index,
+        },
+        &cached_tx,
+        &witness_layouts,
+        otx_starts,
+        &script_hashes_cache,
+        &LockMatcher::by_script_hash(current_script_hash, &script_hashes_cache),
+        &CobuildLimits::default(),
+    )?;
+    if !otx_verification
+        .matched_witness_indices
+        .contains(&witness_index)
+    {
+        log!(
+            "OTX at index {} doesn't contain the current script",
+            witness_index
+        );
+        return Err(Error::NoSealFound);
+    }
+    Ok(())
+}
+
+/// Passes to `handler` only the ranges of the OTX at `witness_index`.
+struct OnlyWitness<H> {
+    handler: H,
+    witness_index: usize,
+}
+
+impl<H: OtxRangeHandler> OtxRangeHandler for OnlyWitness<H> {
+    fn handle(
+        &mut self,
+        otx: &basic::Otx,
+        signing_range: OtxSigningRange,
+        otx_context: &OtxContext,
+    ) -> Result<bool, Error> {
+        if otx_context.witness_index != self.witness_index {
+            return Ok(false);
+        }
+        self.handler.handle(otx, signing_range, otx_context)
+    }
+}
+
+/// Returns the signing ranges `script_hash` is responsible for in the OTXs of
+/// the current transaction, in witness order: one for each OTX containing the
+/// script in its fixed inputs, and one more covering the fixed and dynamic
+/// parts for each OTX containing the script in its dynamic inputs, or as
+/// lock or type of its dynamic outputs. A wallet
+/// can use them to show which cells and deps a signature authorizes.
+///
+/// OTXs are walked and checked exactly like in `verify_otx_message`, but no
+/// signing message hash is computed and no seal is looked up or verified, so
+/// it works before the transaction is signed. Inputs outside of OTXs are not
+/// covered.
+pub fn otx_signing_ranges_for(script_hash: [u8; 32]) -> Result<Vec<OtxSigningRange>, Error> {
+    let cached_tx = CachedTransaction::without_cache();
+    let (witness_layouts, activated) = parse_witness_layouts(cached_tx.transaction())?;
+    if !activated {
+        return Ok(Vec::new());
+    }
+    let otx_starts = fetch_otx_start(&witness_layouts)?;
+    if otx_starts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let script_hashes_cache = cache_script_hashes_with_cell_deps();
+    let mut ranges = Vec::new();
+    verify_otx_segments(
+        &mut ranges,
+        &cached_tx,
+        &witness_layouts,
+        otx_starts,
+        &script_hashes_cache,
+        &LockMatcher::by_script_hash(script_hash, &script_hashes_cache),
+        &CobuildLimits::default(),
+    )?;
+    Ok(ranges)
+}
+
+/// Returns whether every input cell locked by `script_hash` is covered by an
+/// OTX of the current transaction, so `cobuild_entry` doesn't need a
+/// `SighashAll` or `SighashAllOnly` witness for it. It's also true when the
+/// script locks no input. Like `otx_signing_ranges_for`, OTXs are checked but
+/// no seal is verified, so it works before the transaction is signed.
+pub fn is_fully_otx_covered(script_hash: [u8; 32]) -> Result<bool, Error> {
+    let cached_tx = CachedTransaction::without_cache();
+    let (witness_layouts, activated) = parse_witness_layouts(cached_tx.transaction())?;
+    let otx_starts = if activated {
+        fetch_otx_start(&witness_layouts)?
+    } else {
+        Vec::new()
+    };
+    let script_hashes_cache = cache_script_hashes_with_cell_deps();
+    let input_ranges = if otx_starts.is_empty() {
+        Vec::new()
+    } else {
+        verify_otx_segments(
+            &mut Vec::<OtxSigningRange>::new(),
+            &cached_tx,
+            &witness_layouts,
+            otx_starts,
+            &script_hashes_cache,
+            &LockMatcher::by_script_hash(script_hash, &script_hashes_cache),
+            &CobuildLimits::default(),
+        )?
+        .input_ranges
+    };
+    let inputs_len = cached_tx.transaction().raw()?.inputs()?.len()?;
+    Ok(!input_lock_outside_otxs(
+        &script_hashes_cache,
+        &script_hash,
+        &input_ranges,
+        inputs_len,
+    ))
+}
+
+/// Same as `verify_otx_message`, but takes a closure as verifier. The closure
+/// receives the seal and the signing message hash.
+pub fn verify_otx_message_fn<F: FnMut(&[u8], &[u8; 32]) -> Result<(), Error>>(
+    current_script_hash: [u8; 32],
+    f: F,
+) -> Result<bool, Error> {
+    verify_otx_message(current_script_hash, FnCallback(f))
 }
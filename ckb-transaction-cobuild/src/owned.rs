@@ -0,0 +1,42 @@
+//! Owned copies of lazy-reader-backed message types.
+//!
+//! The `schemas2` types read their fields through syscalls on demand. The
+//! types here materialize them into plain structs, which can be kept around,
+//! compared and, with the `serde` feature, serialized.
+use alloc::vec::Vec;
+
+use crate::{error::Error, schemas2::basic};
+
+/// An owned copy of `basic::Action`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedAction {
+    pub script_info_hash: [u8; 32],
+    pub script_hash: [u8; 32],
+    pub script_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<&basic::Action> for OwnedAction {
+    type Error = Error;
+
+    fn try_from(action: &basic::Action) -> Result<Self, Self::Error> {
+        Ok(OwnedAction {
+            script_info_hash: action.script_info_hash()?,
+            script_hash: action.script_hash()?,
+            script_type: action.script_type()?,
+            data: action.data()?.try_into()?,
+        })
+    }
+}
+
+impl basic::Message {
+    /// Reads all actions of the message into owned structs.
+    pub fn to_owned_actions(&self) -> Result<Vec<OwnedAction>, Error> {
+        let mut actions = Vec::new();
+        for action in self.actions()?.iter() {
+            actions.push(OwnedAction::try_from(&action)?);
+        }
+        Ok(actions)
+    }
+}
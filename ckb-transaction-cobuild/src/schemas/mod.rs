@@ -1,3 +1,4 @@
+#![allow(clippy::all)]
 pub use ckb_gen_types::packed as blockchain;
 pub mod basic;
 pub mod top_level;
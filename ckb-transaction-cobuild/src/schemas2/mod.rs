@@ -1,4 +1,4 @@
-#![allow(unused_imports)]
+#![allow(unused_imports, clippy::all, mismatched_lifetime_syntaxes)]
 pub mod basic;
 pub mod blockchain;
 pub mod top_level;
@@ -0,0 +1,115 @@
+//! Typed seals.
+//!
+//! Seals are opaque bytes to the cobuild flow, and `Callback::invoke` receives
+//! them as is. A lock supporting several signature schemes can prefix the
+//! seal with a scheme byte and implement `SealCallback` instead, wrapped in
+//! `ParseSeal`, to receive the parsed `Seal`.
+//!
+//! A seal is only tagged when its length matches the scheme exactly, so
+//! untagged seals of other lengths are kept as `Seal::Raw`. Untagged seals of
+//! a tagged length are ambiguous, so only locks whose signers always add the
+//! scheme byte should use this module.
+use alloc::vec::Vec;
+
+use crate::{error::Error, otx::OtxContext, Callback};
+
+/// Scheme byte of a 65-byte recoverable secp256k1 signature.
+pub const SEAL_SCHEME_SECP256K1_RECOVERABLE: u8 = 0x01;
+/// Scheme byte of a 64-byte schnorr signature.
+pub const SEAL_SCHEME_SCHNORR: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Seal {
+    Secp256k1Recoverable([u8; 65]),
+    Schnorr([u8; 64]),
+    /// Any other seal, without the scheme byte being interpreted.
+    Raw(Vec<u8>),
+}
+
+impl Seal {
+    /// Returns the seal bytes, with the scheme byte for tagged seals. It's the
+    /// inverse of `try_from`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (scheme, signature) = match self {
+            Seal::Secp256k1Recoverable(signature) => {
+                (SEAL_SCHEME_SECP256K1_RECOVERABLE, &signature[..])
+            }
+            Seal::Schnorr(signature) => (SEAL_SCHEME_SCHNORR, &signature[..]),
+            Seal::Raw(seal) => return seal.clone(),
+        };
+        let mut bytes = Vec::with_capacity(1 + signature.len());
+        bytes.push(scheme);
+        bytes.extend_from_slice(signature);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Seal {
+    type Error = Error;
+
+    /// Returns `Error::DataLength` for an empty seal, which carries no scheme.
+    fn try_from(seal: &[u8]) -> Result<Self, Self::Error> {
+        let (scheme, signature) = seal.split_first().ok_or(Error::DataLength)?;
+        let tagged = match *scheme {
+            SEAL_SCHEME_SECP256K1_RECOVERABLE => {
+                signature.try_into().ok().map(Seal::Secp256k1Recoverable)
+            }
+            SEAL_SCHEME_SCHNORR => signature.try_into().ok().map(Seal::Schnorr),
+            _ => None,
+        };
+        Ok(tagged.unwrap_or_else(|| Seal::Raw(seal.to_vec())))
+    }
+}
+
+/// Like `Callback`, but the verifier receives the parsed `Seal`. Use it
+/// through `ParseSeal`.
+pub trait SealCallback {
+    fn invoke_seal(&mut self, seal: &Seal, signing_message_hash: &[u8; 32]) -> Result<(), Error>;
+
+    /// Invoked for OTX groups instead of `invoke_seal`, with the context of
+    /// the group being verified. Forwards to `invoke_seal` by default.
+    fn invoke_seal_otx(
+        &mut self,
+        seal: &Seal,
+        signing_message_hash: &[u8; 32],
+        _ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        self.invoke_seal(seal, signing_message_hash)
+    }
+}
+
+impl<T: SealCallback + ?Sized> SealCallback for &mut T {
+    fn invoke_seal(&mut self, seal: &Seal, signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        (**self).invoke_seal(seal, signing_message_hash)
+    }
+
+    fn invoke_seal_otx(
+        &mut self,
+        seal: &Seal,
+        signing_message_hash: &[u8; 32],
+        ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        (**self).invoke_seal_otx(seal, signing_message_hash, ctx)
+    }
+}
+
+/// Adapts a `SealCallback` to `Callback` by parsing every seal, e.g.
+/// `cobuild_entry(ParseSeal(&mut verifier))`.
+pub struct ParseSeal<F>(pub F);
+
+impl<F: SealCallback> Callback for ParseSeal<F> {
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        self.0
+            .invoke_seal(&Seal::try_from(seal)?, signing_message_hash)
+    }
+
+    fn invoke_otx(
+        &mut self,
+        seal: &[u8],
+        signing_message_hash: &[u8; 32],
+        ctx: &OtxContext,
+    ) -> Result<(), Error> {
+        self.0
+            .invoke_seal_otx(&Seal::try_from(seal)?, signing_message_hash, ctx)
+    }
+}
@@ -0,0 +1,19 @@
+//! The syscalls used by this crate. They are the ones of `ckb_std`, unless the
+//! `mock-syscalls` feature is enabled, in which case they read the transaction
+//! set by `mock::set_mock_transaction`.
+
+#[cfg(not(feature = "mock-syscalls"))]
+pub(crate) use ckb_std::{
+    high_level::{
+        load_cell_lock, load_cell_lock_hash, load_cell_type, load_cell_type_hash, load_script,
+        load_script_hash, load_tx_hash, load_witness_args,
+    },
+    syscalls::{load_cell, load_cell_data, load_transaction, load_witness},
+};
+
+#[cfg(feature = "mock-syscalls")]
+pub(crate) use crate::mock::{
+    load_cell, load_cell_data, load_cell_lock, load_cell_lock_hash, load_cell_type,
+    load_cell_type_hash, load_script, load_script_hash, load_transaction, load_tx_hash,
+    load_witness, load_witness_args,
+};
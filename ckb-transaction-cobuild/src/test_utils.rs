@@ -0,0 +1,131 @@
+//! Helpers to build cobuild witnesses in integration tests of downstream
+//! scripts.
+//!
+//! `MessageWitnesses` places a `SighashAll` or `SighashAllOnly` witness for
+//! each lock script group, given the input indices of the group, and signs
+//! them with the signing message hash computed by the `host` module. Other
+//! witnesses, e.g. the OTX witnesses returned by the `builder` module, are
+//! appended after the inputs.
+use alloc::vec::Vec;
+use ckb_gen_types::prelude::*;
+use molecule::bytes::Bytes;
+
+use crate::{
+    error::Error,
+    host,
+    schemas::{
+        basic::{Message, ResolvedInputs, SighashAll, SighashAllOnly},
+        blockchain::{BytesVec, Transaction},
+        top_level::{WitnessLayout, WitnessLayoutUnion},
+    },
+};
+
+/// Witness of one lock script group.
+pub struct GroupWitness {
+    /// Input indices of the group. The witness is placed at the first one,
+    /// the witnesses at the others are left empty.
+    pub input_indices: Vec<usize>,
+    /// `Some` for a `SighashAll` witness, `None` for `SighashAllOnly`.
+    pub message: Option<Message>,
+    pub seal: Bytes,
+}
+
+impl GroupWitness {
+    /// Returns the `SighashAll` or `SighashAllOnly` witness of the group.
+    pub fn witness_layout(&self) -> WitnessLayout {
+        let union = match &self.message {
+            Some(message) => WitnessLayoutUnion::SighashAll(
+                SighashAll::new_builder()
+                    .message(message.clone())
+                    .seal(self.seal.pack())
+                    .build(),
+            ),
+            None => WitnessLayoutUnion::SighashAllOnly(
+                SighashAllOnly::new_builder().seal(self.seal.pack()).build(),
+            ),
+        };
+        WitnessLayout::new_builder().set(union).build()
+    }
+}
+
+/// The cobuild witnesses of a transaction.
+#[derive(Default)]
+pub struct MessageWitnesses {
+    pub groups: Vec<GroupWitness>,
+    /// Witnesses beyond the inputs count, in order.
+    pub others: Vec<Bytes>,
+}
+
+impl MessageWitnesses {
+    /// Creates the witnesses of lock script groups, each given by its input
+    /// indices. All groups start as `SighashAllOnly` with an empty seal.
+    pub fn new(groups: Vec<Vec<usize>>) -> Self {
+        Self {
+            groups: groups
+                .into_iter()
+                .map(|input_indices| GroupWitness {
+                    input_indices,
+                    message: None,
+                    seal: Bytes::new(),
+                })
+                .collect(),
+            others: Vec::new(),
+        }
+    }
+
+    /// Turns the witness of `group` into a `SighashAll` carrying `message`.
+    /// Only one group of a transaction can carry a message.
+    pub fn set_with_action(&mut self, group: usize, message: Message) {
+        self.groups[group].message = Some(message);
+    }
+
+    /// Returns the message of the `SighashAll` witness, if any.
+    pub fn message(&self) -> Option<Message> {
+        self.groups.iter().find_map(|g| g.message.clone())
+    }
+
+    /// Returns all witnesses of a transaction with `inputs_len` inputs.
+    pub fn witnesses(&self, inputs_len: usize) -> Vec<Bytes> {
+        let mut witnesses = alloc::vec![Bytes::new(); inputs_len];
+        for group in &self.groups {
+            if let Some(first) = group.input_indices.first() {
+                witnesses[*first] = group.witness_layout().as_bytes();
+            }
+        }
+        witnesses.extend(self.others.iter().cloned());
+        witnesses
+    }
+
+    /// Signs every group with `sign`, which receives the group index and the
+    /// signing message hash and returns the seal. Then returns `tx` with the
+    /// witnesses set. Call it again after mutating the witnesses.
+    pub fn sign<F: FnMut(usize, &[u8; 32]) -> Bytes>(
+        &mut self,
+        tx: &Transaction,
+        resolved_inputs: &ResolvedInputs,
+        mut sign: F,
+    ) -> Result<Transaction, Error> {
+        let inputs_len = tx.raw().inputs().len();
+        // the signing message hash covers the witnesses beyond the inputs
+        // count, but not the group witnesses
+        let tx = self.apply(tx, inputs_len);
+        let signing_message_hash =
+            host::signing_message_hash(&self.message(), &tx, resolved_inputs)?;
+        for (index, group) in self.groups.iter_mut().enumerate() {
+            group.seal = sign(index, &signing_message_hash);
+        }
+        Ok(self.apply(&tx, inputs_len))
+    }
+
+    fn apply(&self, tx: &Transaction, inputs_len: usize) -> Transaction {
+        let witnesses = BytesVec::new_builder()
+            .set(
+                self.witnesses(inputs_len)
+                    .into_iter()
+                    .map(|w| w.pack())
+                    .collect(),
+            )
+            .build();
+        tx.clone().as_builder().witnesses(witnesses).build()
+    }
+}
@@ -1,72 +1,320 @@
 use alloc::{collections::btree_map::BTreeMap, vec::Vec};
 use ckb_std::{
     ckb_constants::Source,
-    high_level::{load_cell_lock_hash, load_cell_type_hash, QueryIter},
+    ckb_types::{packed::Script, prelude::*},
+    error::SysError,
+    high_level::QueryIter,
 };
 
-use crate::{error::Error, schemas2::basic::Message};
+use crate::{
+    blake2b::{new_blake2b_with_personal, PERSONALIZATION_DIGEST},
+    error::Error,
+    lazy_reader::Cursor,
+    log,
+    log::Hex,
+    schemas2::{
+        basic::{self, Message},
+        top_level::WitnessLayout,
+    },
+    syscalls::{
+        load_cell_lock, load_cell_lock_hash, load_cell_type, load_cell_type_hash, load_script,
+    },
+};
+
+/// Compares two byte slices without short-circuiting on the first difference.
+/// Slices of different lengths are never equal; only the contents are compared
+/// in constant time.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Same as `ct_eq`, for script hashes.
+pub fn ct_eq_32(a: &[u8], b: &[u8; 32]) -> bool {
+    ct_eq(a, b)
+}
 
 #[derive(Debug)]
 pub enum ScriptType {
     InputLock,
     InputType,
     OutputType,
+    OutputLock,
+    /// the script is used as type script in both inputs and outputs, e.g. to
+    /// check a state transition
+    InputOutputType,
+    /// the script is the lock of a cell dep, e.g. a governance cell
+    /// authorizing the action. Cell deps are only in the cache of
+    /// `cache_script_hashes_with_cell_deps`.
+    CellDepLock,
+    /// the script is the type of a cell dep
+    CellDepType,
 }
 
-#[derive(Debug)]
+/// Where a script is used in the transaction. Each vector holds the cell
+/// indices in ascending order.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct ScriptLocation {
+    /// indices of the input cells using the script as lock
     pub input_lock: Vec<usize>,
+    /// indices of the input cells using the script as type
     pub input_type: Vec<usize>,
+    /// indices of the output cells using the script as type
     pub output_type: Vec<usize>,
+    /// indices of the output cells using the script as lock
+    pub output_lock: Vec<usize>,
+    /// indices of the cell deps using the script as lock
+    pub cell_dep_lock: Vec<usize>,
+    /// indices of the cell deps using the script as type
+    pub cell_dep_type: Vec<usize>,
 }
 
+/// Returns the first `N` bytes of the args of the current script, e.g. a
+/// 20-byte pubkey hash. Args longer than `N` are accepted, the remaining
+/// bytes can carry other parameters. Returns `Error::DataLength` if the args
+/// are shorter than `N`.
+pub fn load_args_array<const N: usize>() -> Result<[u8; N], Error> {
+    let args = load_script()?.args().raw_data();
+    if args.len() < N {
+        log!("script args have {} bytes, {} expected", args.len(), N);
+        return Err(Error::DataLength);
+    }
+    Ok(args[..N].try_into()?)
+}
+
+/// Collects the indices where each script hash appears as input lock, input
+/// type, output type and output lock. It performs a full linear scan of all
+/// inputs and outputs with syscalls, so call it once and reuse the result.
+/// Cell deps aren't scanned, so the cell dep vectors are always empty.
 pub fn cache_script_hashes() -> BTreeMap<[u8; 32], ScriptLocation> {
+    cache_scripts(
+        load_cell_lock_hash,
+        load_cell_type_hash,
+        TX_CELLS,
+        usize::MAX,
+    )
+    .0
+}
+
+/// Same as `cache_script_hashes`, but also scans the cell deps, e.g. to
+/// validate actions of `ScriptType::CellDepLock` or `ScriptType::CellDepType`.
+/// The entry points build their cache with it, so messages checked by
+/// `cobuild_entry` and the OTX verification functions can name cell deps.
+pub fn cache_script_hashes_with_cell_deps() -> BTreeMap<[u8; 32], ScriptLocation> {
+    cache_scripts(
+        load_cell_lock_hash,
+        load_cell_type_hash,
+        ALL_CELLS,
+        usize::MAX,
+    )
+    .0
+}
+
+/// Same as `cache_script_hashes`, but stops after scanning `max_cells` cells,
+/// inputs first, then outputs. Returns whether the transaction has more
+/// cells, in which case the cache only covers the first ones. A lock can
+/// reject such transactions instead of spending unbounded cycles on them:
+///
+/// ```ignore
+/// let (cache, truncated) = cache_script_hashes_capped(512);
+/// if truncated {
+///     return Err(Error::WrongCount);
+/// }
+/// ```
+pub fn cache_script_hashes_capped(max_cells: usize) -> (BTreeMap<[u8; 32], ScriptLocation>, bool) {
+    cache_scripts(
+        load_cell_lock_hash,
+        load_cell_type_hash,
+        TX_CELLS,
+        max_cells,
+    )
+}
+
+/// Same as `cache_script_hashes`, but only scans the cells of the current
+/// script group, with `Source::GroupInput` and `Source::GroupOutput`. The
+/// indices are relative to the group, e.g. `output_lock == [0]` means the
+/// first output of the group, not output 0 of the transaction.
+///
+/// Use it in a type script checking the locks and types of its own cells.
+/// Actions and OTX ranges refer to transaction indices, so they must be
+/// checked against `cache_script_hashes`. A lock script group has no outputs,
+/// so the output vectors are always empty for locks. Cell deps belong to no
+/// group, so the cell dep vectors are always empty.
+pub fn cache_group_script_hashes() -> BTreeMap<[u8; 32], ScriptLocation> {
+    cache_scripts(
+        load_cell_lock_hash,
+        load_cell_type_hash,
+        GROUP_CELLS,
+        usize::MAX,
+    )
+    .0
+}
+
+/// Same as `cache_script_hashes`, but keyed by `code_hash_key`, so scripts
+/// sharing the same code with different args share the same entry. It loads
+/// the full scripts, which costs more cycles than loading their hashes.
+pub fn cache_by_code_hash() -> BTreeMap<[u8; 32], ScriptLocation> {
+    cache_scripts(
+        |index, source| load_cell_lock(index, source).map(|script| code_hash_key(&script)),
+        |index, source| {
+            load_cell_type(index, source).map(|script| script.map(|script| code_hash_key(&script)))
+        },
+        TX_CELLS,
+        usize::MAX,
+    )
+    .0
+}
+
+/// Returns the key of `script` in `cache_by_code_hash`: the hash of its
+/// `code_hash` and `hash_type`, ignoring `args`.
+pub fn code_hash_key(script: &Script) -> [u8; 32] {
+    let mut hasher = new_blake2b_with_personal(PERSONALIZATION_DIGEST);
+    hasher.update(script.code_hash().as_slice());
+    hasher.update(script.hash_type().as_slice());
+    let mut result = [0u8; 32];
+    hasher.finalize(&mut result);
+    result
+}
+
+/// Selects the vector of a `ScriptLocation` receiving the cell indices.
+type LocationField = fn(&mut ScriptLocation) -> &mut Vec<usize>;
+
+/// The sources scanned by `cache_scripts`, in order, with the vectors
+/// receiving the cells using a script as lock and as type.
+const TX_CELLS: &[(Source, LocationField, LocationField)] = &[
+    (Source::Input, |l| &mut l.input_lock, |l| &mut l.input_type),
+    (
+        Source::Output,
+        |l| &mut l.output_lock,
+        |l| &mut l.output_type,
+    ),
+];
+const ALL_CELLS: &[(Source, LocationField, LocationField)] = &[
+    (Source::Input, |l| &mut l.input_lock, |l| &mut l.input_type),
+    (
+        Source::Output,
+        |l| &mut l.output_lock,
+        |l| &mut l.output_type,
+    ),
+    (
+        Source::CellDep,
+        |l| &mut l.cell_dep_lock,
+        |l| &mut l.cell_dep_type,
+    ),
+];
+const GROUP_CELLS: &[(Source, LocationField, LocationField)] = &[
+    (
+        Source::GroupInput,
+        |l| &mut l.input_lock,
+        |l| &mut l.input_type,
+    ),
+    (
+        Source::GroupOutput,
+        |l| &mut l.output_lock,
+        |l| &mut l.output_type,
+    ),
+];
+
+fn cache_scripts<L, T>(
+    load_lock: L,
+    load_type: T,
+    sources: &[(Source, LocationField, LocationField)],
+    max_cells: usize,
+) -> (BTreeMap<[u8; 32], ScriptLocation>, bool)
+where
+    L: Fn(usize, Source) -> Result<[u8; 32], SysError> + Copy,
+    T: Fn(usize, Source) -> Result<Option<[u8; 32]>, SysError> + Copy,
+{
     let mut script_hashes_cache: BTreeMap<[u8; 32], ScriptLocation> = BTreeMap::new();
 
-    QueryIter::new(load_cell_lock_hash, Source::Input)
-        .enumerate()
-        .for_each(|(index, lock_hash)| {
-            script_hashes_cache
-                .entry(lock_hash)
-                .and_modify(|location| location.input_lock.push(index))
-                .or_insert(ScriptLocation {
-                    input_lock: [index].to_vec(),
-                    input_type: Vec::new(),
-                    output_type: Vec::new(),
-                });
-        });
+    let mut remaining = max_cells;
+    // how many cells of each source are scanned
+    let mut counts = Vec::with_capacity(sources.len());
+    for (source, lock_field, type_field) in sources {
+        let mut count = 0;
+        QueryIter::new(load_lock, *source)
+            .take(remaining)
+            .enumerate()
+            .for_each(|(index, lock_hash)| {
+                lock_field(script_hashes_cache.entry(lock_hash).or_default()).push(index);
+                count = index + 1;
+            });
 
-    QueryIter::new(load_cell_type_hash, Source::Input)
-        .enumerate()
-        .for_each(|(index, input_type_hash)| {
-            if let Some(input_type_hash) = input_type_hash {
-                script_hashes_cache
-                    .entry(input_type_hash)
-                    .and_modify(|location| location.input_type.push(index))
-                    .or_insert(ScriptLocation {
-                        input_lock: Vec::new(),
-                        input_type: [index].to_vec(),
-                        output_type: Vec::new(),
-                    });
-            }
-        });
+        QueryIter::new(load_type, *source)
+            .take(count)
+            .enumerate()
+            .for_each(|(index, type_hash)| {
+                if let Some(type_hash) = type_hash {
+                    type_field(script_hashes_cache.entry(type_hash).or_default()).push(index);
+                }
+            });
+        remaining -= count;
+        counts.push(count);
+    }
 
-    QueryIter::new(load_cell_type_hash, Source::Output)
-        .enumerate()
-        .for_each(|(index, output_type_hash)| {
-            if let Some(output_type_hash) = output_type_hash {
-                script_hashes_cache
-                    .entry(output_type_hash)
-                    .and_modify(|location| location.output_type.push(index))
-                    .or_insert(ScriptLocation {
-                        input_lock: Vec::new(),
-                        input_type: Vec::new(),
-                        output_type: [index].to_vec(),
-                    });
-            }
+    // when the cap is reached, the cells left are not cached
+    let truncated = remaining == 0
+        && sources.iter().zip(counts).any(|((source, _, _), count)| {
+            !matches!(load_lock(count, *source), Err(SysError::IndexOutOfBound))
         });
+    (script_hashes_cache, truncated)
+}
 
-    script_hashes_cache
+/// Returns where `script_hash` is used, or `None` if it's absent from the
+/// transaction. Unlike `is_script_exist`, it gives the exact positions, e.g.
+/// to enforce that a type script is only at input 0 and output 0:
+///
+/// ```ignore
+/// let location = script_location(&cache, &hash).ok_or(Error::ScriptHashAbsent)?;
+/// let only_first = location.input_type == [0] && location.output_type == [0];
+/// ```
+///
+/// Caches built by `cache_script_hashes` never contain entries with all
+/// vectors empty, but hand-built ones can.
+pub fn script_location<'a>(
+    script_hashes_cache: &'a BTreeMap<[u8; 32], ScriptLocation>,
+    script_hash: &[u8; 32],
+) -> Option<&'a ScriptLocation> {
+    script_hashes_cache.get(script_hash)
+}
+
+/// Returns all locations of the script named by `action`, regardless of the
+/// declared script type, e.g. for a type script to tell whether a hash used
+/// both as input lock and output type is addressed in the right role. Returns
+/// `Error::ScriptHashAbsent` if the script isn't used in the transaction.
+pub fn action_locations(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    action: &basic::Action,
+) -> Result<ScriptLocation, Error> {
+    let script_hash = action.script_hash()?;
+    script_location(script_hashes_cache, &script_hash)
+        .cloned()
+        .ok_or_else(|| {
+            log!("action script hash {} is absent", Hex(&script_hash));
+            Error::ScriptHashAbsent
+        })
+}
+
+/// Checks that the script named by `script_hash` is the lock of exactly one
+/// input cell, for locks forbidding several cells in the same transaction.
+/// Returns `Error::WrongCount` otherwise, including when it isn't used as an
+/// input lock at all.
+///
+/// A transaction made of several OTXs, each unlocking a cell of the same lock,
+/// violates this legitimately, so it doesn't suit locks supporting OTXs.
+pub fn assert_single_lock_instance(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    script_hash: &[u8; 32],
+) -> Result<(), Error> {
+    let count = script_location(script_hashes_cache, script_hash)
+        .map_or(0, |location| location.input_lock.len());
+    if count != 1 {
+        log!("lock {} is used by {} inputs", Hex(script_hash), count);
+        return Err(Error::WrongCount);
+    }
+    Ok(())
 }
 
 pub fn is_script_exist(
@@ -80,9 +328,38 @@ pub fn is_script_exist(
             ScriptType::InputLock => !location.input_lock.is_empty(),
             ScriptType::InputType => !location.input_type.is_empty(),
             ScriptType::OutputType => !location.output_type.is_empty(),
+            ScriptType::OutputLock => !location.output_lock.is_empty(),
+            ScriptType::InputOutputType => {
+                !location.input_type.is_empty() && !location.output_type.is_empty()
+            }
+            ScriptType::CellDepLock => !location.cell_dep_lock.is_empty(),
+            ScriptType::CellDepType => !location.cell_dep_type.is_empty(),
+        })
+}
+
+/// Returns how many cells use `script_hash` as `script_type`. For
+/// `ScriptType::InputOutputType`, input and output cells are counted together.
+pub fn count_script_cells(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    script_hash: [u8; 32],
+    script_type: ScriptType,
+) -> usize {
+    script_hashes_cache
+        .get(&script_hash)
+        .map_or(0, |location| match script_type {
+            ScriptType::InputLock => location.input_lock.len(),
+            ScriptType::InputType => location.input_type.len(),
+            ScriptType::OutputType => location.output_type.len(),
+            ScriptType::OutputLock => location.output_lock.len(),
+            ScriptType::InputOutputType => location.input_type.len() + location.output_type.len(),
+            ScriptType::CellDepLock => location.cell_dep_lock.len(),
+            ScriptType::CellDepType => location.cell_dep_type.len(),
         })
 }
 
+/// Returns whether `script_hash` appears as `script_type` at any index in
+/// `[start_index, end_index)`. For `ScriptType::InputOutputType`, the range
+/// applies to the input cells and the script must also appear in any output.
 pub fn is_script_included(
     script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
     script_hash: [u8; 32],
@@ -92,38 +369,299 @@ pub fn is_script_included(
 ) -> bool {
     script_hashes_cache
         .get(&script_hash)
-        .is_some_and(|location| match script_type {
-            ScriptType::InputLock => !location
-                .input_lock
-                .iter()
-                .any(|loc| *loc >= start_index && *loc < end_index),
-            ScriptType::InputType => !location
-                .input_type
-                .iter()
-                .any(|loc| *loc >= start_index && *loc < end_index),
-            ScriptType::OutputType => !location
-                .output_type
+        .is_some_and(|location| {
+            let indices = match script_type {
+                ScriptType::InputLock => &location.input_lock,
+                ScriptType::InputType => &location.input_type,
+                ScriptType::OutputType => &location.output_type,
+                ScriptType::OutputLock => &location.output_lock,
+                ScriptType::InputOutputType => {
+                    if location.output_type.is_empty() {
+                        return false;
+                    }
+                    &location.input_type
+                }
+                ScriptType::CellDepLock => &location.cell_dep_lock,
+                ScriptType::CellDepType => &location.cell_dep_type,
+            };
+            indices
                 .iter()
-                .any(|loc| *loc >= start_index && *loc < end_index),
+                .any(|loc| *loc >= start_index && *loc < end_index)
         })
 }
 
+/// Checks that the script of every action in `message` exists in the
+/// transaction with the action's script type. Same as `validate_actions`,
+/// without the index of the failing action.
 pub fn check_message(
     script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
     message: Message,
 ) -> Result<(), Error> {
-    for action in message.actions()?.iter() {
-        let script_type = match action.script_type()? {
-            0 => ScriptType::InputLock,
-            1 => ScriptType::InputType,
-            2 => ScriptType::OutputType,
-            _ => return Err(Error::WrongScriptType),
-        };
-
-        if !is_script_exist(script_hashes_cache, action.script_hash()?, script_type) {
+    validate_actions(script_hashes_cache, &message).map_err(|(_, err)| err)
+}
+
+/// Same as `check_message`, but also requires every action to name
+/// `self_hash`, usually the hash of the current type script, so only actions
+/// addressed to it are accepted. An action naming another script fails with
+/// `Error::ScriptHashAbsent`.
+pub fn check_message_self(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    message: Message,
+    self_hash: &[u8; 32],
+) -> Result<(), Error> {
+    for (index, action) in message.actions_indexed()? {
+        validate_action(script_hashes_cache, &action)?;
+        if !ct_eq_32(&action.script_hash()?, self_hash) {
+            log!("action {} doesn't name the current script", index);
             return Err(Error::ScriptHashAbsent);
         }
     }
+    Ok(())
+}
+
+/// Same as `check_message`, but returns the index of the failing action along
+/// with the error. Errors of the message itself are reported at the index of
+/// the action being read.
+pub fn validate_actions(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    message: &Message,
+) -> Result<(), (usize, Error)> {
+    let actions = message.actions().map_err(|err| (0, err.into()))?;
+    let len = actions.len().map_err(|err| (0, err.into()))?;
+    for index in 0..len {
+        let action = actions.get(index).map_err(|err| (index, err.into()))?;
+        validate_action(script_hashes_cache, &action).map_err(|err| (index, err))?;
+    }
+
+    Ok(())
+}
 
+fn validate_action(
+    script_hashes_cache: &BTreeMap<[u8; 32], ScriptLocation>,
+    action: &basic::Action,
+) -> Result<(), Error> {
+    let script_type = match action.script_type()? {
+        0 => ScriptType::InputLock,
+        1 => ScriptType::InputType,
+        2 => ScriptType::OutputType,
+        3 => ScriptType::OutputLock,
+        4 => ScriptType::InputOutputType,
+        5 => ScriptType::CellDepLock,
+        6 => ScriptType::CellDepType,
+        _ => return Err(Error::WrongScriptType),
+    };
+
+    let script_hash = action.script_hash()?;
+    if !is_script_exist(script_hashes_cache, script_hash, script_type) {
+        log!("action script hash {} is absent", Hex(&script_hash));
+        return Err(Error::ScriptHashAbsent);
+    }
     Ok(())
 }
+
+impl basic::Action {
+    /// Returns the blake2b hash of `script_hash || script_type || data`, e.g.
+    /// to detect duplicated actions with a `BTreeSet`. It's a content digest,
+    /// not a signing message hash, and is never signed.
+    pub fn action_digest(&self) -> Result<[u8; 32], Error> {
+        let mut hasher = new_blake2b_with_personal(PERSONALIZATION_DIGEST);
+        hasher.update(&self.script_hash()?);
+        hasher.update(&[self.script_type()?]);
+        hasher.update_cursor(self.data()?)?;
+        let mut result = [0u8; 32];
+        hasher.finalize(&mut result);
+        Ok(result)
+    }
+
+    /// Reads the action data as a fixed size payload, e.g. a 16-byte amount.
+    /// Returns `Error::DataLength` if the data isn't exactly `N` bytes long.
+    pub fn data_array<const N: usize>(&self) -> Result<[u8; N], Error> {
+        let data = self.data()?;
+        if data.size != N {
+            return Err(Error::DataLength);
+        }
+        let mut buf = [0u8; N];
+        if N > 0 && data.read_at(&mut buf)? != N {
+            return Err(Error::DataLength);
+        }
+        Ok(buf)
+    }
+}
+
+impl basic::Message {
+    /// Returns the blake2b hash of the molecule encoded message. Like
+    /// `Action::action_digest`, it's a content digest, not a signing message
+    /// hash.
+    pub fn message_digest(&self) -> Result<[u8; 32], Error> {
+        let mut hasher = new_blake2b_with_personal(PERSONALIZATION_DIGEST);
+        hasher.update_cursor(self.cursor.clone())?;
+        let mut result = [0u8; 32];
+        hasher.finalize(&mut result);
+        Ok(result)
+    }
+    /// Returns the actions of the message along with their indices. Unlike
+    /// `ActionVec::iter`, which panics, a malformed action is returned as an
+    /// error.
+    pub fn actions_indexed(&self) -> Result<Vec<(usize, basic::Action)>, Error> {
+        let actions = self.actions()?;
+        let mut indexed = Vec::new();
+        for index in 0..actions.len()? {
+            indexed.push((index, actions.get(index)?));
+        }
+        Ok(indexed)
+    }
+
+    /// Returns the action at `index`, or `Error::ActionNotFound` if the
+    /// message has fewer actions.
+    pub fn action_at(&self, index: usize) -> Result<basic::Action, Error> {
+        let actions = self.actions()?;
+        if index >= actions.len()? {
+            return Err(Error::ActionNotFound);
+        }
+        Ok(actions.get(index)?)
+    }
+
+    /// Returns the actions whose `script_hash` is `script_hash`, in order.
+    /// The raw 32-byte hashes are compared, the `script_type` of the actions
+    /// is ignored.
+    pub fn actions_for(&self, script_hash: &[u8; 32]) -> Result<Vec<basic::Action>, Error> {
+        let actions = self.actions()?;
+        let mut matched = Vec::new();
+        for index in 0..actions.len()? {
+            let action = actions.get(index)?;
+            if ct_eq_32(&action.script_hash()?, script_hash) {
+                matched.push(action);
+            }
+        }
+        Ok(matched)
+    }
+}
+
+/// Union ids of the `WitnessLayout` members, as assigned in `top_level.mol`.
+/// A witness is classified by its first 4 bytes, in little endian, without
+/// parsing the rest.
+pub const WITNESS_LAYOUT_SIGHASH_ALL: u32 = 0xFF00_0001;
+pub const WITNESS_LAYOUT_SIGHASH_ALL_ONLY: u32 = 0xFF00_0002;
+pub const WITNESS_LAYOUT_OTX: u32 = 0xFF00_0003;
+pub const WITNESS_LAYOUT_OTX_START: u32 = 0xFF00_0004;
+
+/// Union ids of `WitnessLayout` start from this value, so they can't be
+/// confused with the total size at the start of a `WitnessArgs`.
+const WITNESS_LAYOUT_ID_BASE: usize = 0xFF00_0000;
+/// Union ids of the `WitnessLayout` members known by this crate, from
+/// `SighashAll` to `OtxStart`.
+const KNOWN_WITNESS_LAYOUT_IDS: core::ops::RangeInclusive<usize> =
+    WITNESS_LAYOUT_SIGHASH_ALL as usize..=WITNESS_LAYOUT_OTX_START as usize;
+
+/// Returns the union id of `witness` if it is a `WitnessLayout` member unknown
+/// to this crate, e.g. added by a newer cobuild specification. Such a witness
+/// fails to parse as `WitnessLayout` and would otherwise be silently treated
+/// as a non-cobuild witness, so callers can use it to log the unsupported
+/// type.
+pub fn unsupported_witness_layout_id(witness: &Cursor) -> Option<u32> {
+    let item = witness.union_unpack().ok()?;
+    if item.item_id >= WITNESS_LAYOUT_ID_BASE && !KNOWN_WITNESS_LAYOUT_IDS.contains(&item.item_id) {
+        u32::try_from(item.item_id).ok()
+    } else {
+        None
+    }
+}
+
+/// A witness parsed as `WitnessLayout`, including the members unknown to this
+/// crate. `WitnessLayout` is generated from the schema and only has the
+/// members of the specification this crate implements, so `try_from` fails on
+/// a member added later, just like on a `WitnessArgs`. Parsing with
+/// `AnyWitnessLayout::parse` tells them apart, so callers can report or skip
+/// the unsupported types instead of treating them as non-cobuild witnesses.
+pub enum AnyWitnessLayout {
+    Known(WitnessLayout),
+    /// A member added by a newer cobuild specification, with its union id.
+    Unknown(u32),
+}
+
+impl AnyWitnessLayout {
+    /// Returns `None` if `witness` is not a `WitnessLayout`, e.g. a
+    /// `WitnessArgs` or an empty witness.
+    pub fn parse(witness: Cursor) -> Option<Self> {
+        if let Some(id) = unsupported_witness_layout_id(&witness) {
+            return Some(AnyWitnessLayout::Unknown(id));
+        }
+        WitnessLayout::try_from(witness)
+            .ok()
+            .map(AnyWitnessLayout::Known)
+    }
+
+    /// Returns the union id of an unknown member.
+    pub fn unknown_id(&self) -> Option<u32> {
+        match self {
+            AnyWitnessLayout::Unknown(id) => Some(*id),
+            AnyWitnessLayout::Known(_) => None,
+        }
+    }
+
+    /// Returns the layout of a known member.
+    pub fn known(self) -> Option<WitnessLayout> {
+        match self {
+            AnyWitnessLayout::Known(witness_layout) => Some(witness_layout),
+            AnyWitnessLayout::Unknown(_) => None,
+        }
+    }
+}
+
+/// Accessors of the `WitnessLayout` variants, so callers don't need to match
+/// on them. It's also implemented for `Option<WitnessLayout>`, as returned by
+/// `parse_witness_layouts`.
+pub trait WitnessLayoutExt {
+    fn as_sighash_all(&self) -> Option<&basic::SighashAll>;
+    fn as_sighash_all_only(&self) -> Option<&basic::SighashAllOnly>;
+    fn as_otx(&self) -> Option<&basic::Otx>;
+    fn as_otx_start(&self) -> Option<&basic::OtxStart>;
+}
+
+impl WitnessLayoutExt for WitnessLayout {
+    fn as_sighash_all(&self) -> Option<&basic::SighashAll> {
+        match self {
+            WitnessLayout::SighashAll(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_sighash_all_only(&self) -> Option<&basic::SighashAllOnly> {
+        match self {
+            WitnessLayout::SighashAllOnly(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_otx(&self) -> Option<&basic::Otx> {
+        match self {
+            WitnessLayout::Otx(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_otx_start(&self) -> Option<&basic::OtxStart> {
+        match self {
+            WitnessLayout::OtxStart(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl WitnessLayoutExt for Option<WitnessLayout> {
+    fn as_sighash_all(&self) -> Option<&basic::SighashAll> {
+        self.as_ref().and_then(|w| w.as_sighash_all())
+    }
+
+    fn as_sighash_all_only(&self) -> Option<&basic::SighashAllOnly> {
+        self.as_ref().and_then(|w| w.as_sighash_all_only())
+    }
+
+    fn as_otx(&self) -> Option<&basic::Otx> {
+        self.as_ref().and_then(|w| w.as_otx())
+    }
+
+    fn as_otx_start(&self) -> Option<&basic::OtxStart> {
+        self.as_ref().and_then(|w| w.as_otx_start())
+    }
+}
@@ -0,0 +1,60 @@
+use ckb_transaction_cobuild::{
+    error::Error,
+    lazy_reader::{new_transaction, CachedTransaction},
+    parse_witness_layouts,
+    sighashall::{
+        fetch_message, generate_signing_message_hash, generate_signing_message_hash_cached,
+    },
+    utils::{load_args_array, WitnessLayoutExt},
+};
+use core::result::Result;
+
+/// Returns right away, the baseline of the other operations.
+const OP_NONE: u8 = 0;
+/// `generate_signing_message_hash` of a `SighashAllOnly` witness.
+const OP_SIGHASH_ALL_ONLY_HASH: u8 = 1;
+/// `fetch_message` then `generate_signing_message_hash`, which share the
+/// transaction reader.
+const OP_MESSAGE_AND_HASH: u8 = 2;
+/// Same as `OP_MESSAGE_AND_HASH`, with a new transaction reader per step.
+const OP_MESSAGE_AND_HASH_UNSHARED: u8 = 3;
+/// `generate_signing_message_hash_cached` twice on a `CachedTransaction`, so
+/// the second pass reads the input cells memoized by the first one.
+const OP_HASH_TWICE_CACHED: u8 = 4;
+/// Same as `OP_HASH_TWICE_CACHED`, on `CachedTransaction::without_cache`.
+const OP_HASH_TWICE_UNCACHED: u8 = 5;
+
+pub fn main() -> Result<(), Error> {
+    let [operation] = load_args_array::<1>()?;
+    match operation {
+        OP_NONE => {}
+        OP_SIGHASH_ALL_ONLY_HASH => {
+            generate_signing_message_hash(&None)?;
+        }
+        OP_MESSAGE_AND_HASH => {
+            generate_signing_message_hash(&fetch_message()?)?;
+        }
+        OP_MESSAGE_AND_HASH_UNSHARED => {
+            let (witness_layouts, _) = parse_witness_layouts(&new_transaction())?;
+            let message = match witness_layouts.iter().find_map(|w| w.as_sighash_all()) {
+                Some(sighash_all) => Some(sighash_all.message()?),
+                None => None,
+            };
+            generate_signing_message_hash_cached(
+                &CachedTransaction::from_transaction(new_transaction(), 0),
+                &message,
+            )?;
+        }
+        OP_HASH_TWICE_CACHED | OP_HASH_TWICE_UNCACHED => {
+            let cached_tx = if operation == OP_HASH_TWICE_CACHED {
+                CachedTransaction::new()
+            } else {
+                CachedTransaction::without_cache()
+            };
+            generate_signing_message_hash_cached(&cached_tx, &None)?;
+            generate_signing_message_hash_cached(&cached_tx, &None)?;
+        }
+        _ => return Err(Error::DataLength),
+    }
+    Ok(())
+}
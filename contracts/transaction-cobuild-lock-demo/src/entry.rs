@@ -1,26 +1,41 @@
 use ckb_std::{
+    ckb_constants::Source,
     ckb_types::{bytes::Bytes, prelude::*},
-    high_level::load_script,
+    high_level::load_witness_args,
+};
+use ckb_transaction_cobuild::{
+    auth::{verify_secp256k1_blake160, Secp256k1Blake160Verifier},
+    cobuild_auto_entry, current_group_is_cobuild,
+    error::Error,
+    legacy::legacy_signing_message_hash,
+    utils::load_args_array,
 };
-use ckb_transaction_cobuild::sighashall::parse_message;
 use core::result::Result;
 
-use crate::{auth::ckb_auth, error::Error};
-
 pub fn main() -> Result<(), Error> {
-    if let Ok((message_digest, seal)) = parse_message() {
-        let mut pubkey_hash = [0u8; 20];
-        let script = load_script()?;
-        let args: Bytes = script.args().unpack();
-        pubkey_hash.copy_from_slice(&args[0..20]);
+    let pubkey_hash = load_args_array::<20>()?;
 
-        ckb_auth(pubkey_hash, &seal, &message_digest)?;
+    // Other script groups may use cobuild while this one uses the traditional
+    // WitnessArgs, with the seal in its lock field, so decide per group.
+    if !current_group_is_cobuild()? {
+        if let Ok(witness_args) = load_witness_args(0, Source::GroupInput) {
+            let seal: Bytes = witness_args
+                .lock()
+                .to_opt()
+                .ok_or(Error::AuthError)?
+                .unpack();
+            let signing_message_hash = legacy_signing_message_hash()?;
+            return verify_secp256k1_blake160(pubkey_hash, &seal, &signing_message_hash);
+        }
+    }
 
-        Ok(())
-    } else {
-        // In this routine, it indicates that the WitnessLayout is not being
-        // used. It is possible that the traditional WitnessArgs is being used.
-        // The previous code can be copied and pasted here.
-        Ok(())
+    // the demo can also be used as a type script, which only checks the
+    // message
+    let verifier = Secp256k1Blake160Verifier::new(pubkey_hash);
+    let cobuild_activated = cobuild_auto_entry(verifier)?;
+    if !cobuild_activated {
+        // neither a WitnessArgs nor a WitnessLayout
+        return Err(Error::WrongWitnessLayout);
     }
+    Ok(())
 }
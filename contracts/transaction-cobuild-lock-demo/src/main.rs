@@ -3,8 +3,6 @@
 
 // define modules
 mod entry;
-mod error;
-mod auth;
 
 #[cfg(test)]
 extern crate alloc;
@@ -21,6 +19,6 @@ pub fn program_entry() -> i8 {
     // Call main function and return error code
     match entry::main() {
         Ok(_) => 0,
-        Err(err) => err as i8,
+        Err(err) => err.code(),
     }
 }
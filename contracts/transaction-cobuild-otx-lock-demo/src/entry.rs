@@ -1,27 +1,44 @@
 use ckb_std::{
     ckb_types::{bytes::Bytes, prelude::*},
-    high_level::{load_script, load_script_hash},
+    high_level::load_script,
+};
+use ckb_transaction_cobuild::{
+    auth::verify_secp256k1_blake160, cobuild_entry_strict, error::Error, utils::load_args_array,
+    Callback, OtxStrictness,
 };
-use ckb_transaction_cobuild::otx::verify_otx_message;
 use core::result::Result;
 
-use crate::{auth::ckb_auth, error::Error};
+struct Verifier {
+    pubkey_hash: [u8; 20],
+    // number of seals verified so far, across OTX groups and normal entry
+    verified: usize,
+}
+
+impl Callback for Verifier {
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        verify_secp256k1_blake160(self.pubkey_hash, seal, signing_message_hash)?;
+        self.verified += 1;
+        Ok(())
+    }
+}
 
 pub fn main() -> Result<(), Error> {
-    let mut pubkey_hash = [0u8; 20];
-    let script = load_script()?;
-    let args: Bytes = script.args().unpack();
-    let current_script_hash = load_script_hash()?;
-    pubkey_hash.copy_from_slice(&args[0..20]);
+    let pubkey_hash = load_args_array::<20>()?;
+    let args: Bytes = load_script()?.args().unpack();
 
-    let verify = |seal: &[u8], message_digest: &[u8; 32]| {
-        let auth_result = ckb_auth(pubkey_hash, seal, message_digest);
-        auth_result.is_ok()
+    let mut verifier = Verifier {
+        pubkey_hash,
+        verified: 0,
     };
-    let verify_pass = verify_otx_message(current_script_hash, verify)?;
-    if verify_pass {
-        Ok(())
-    } else {
-        Err(Error::AuthFailed)
+    // an optional flag byte after the pubkey hash: 1 rejects OTXs carrying
+    // extra seals for this lock
+    let strictness = match args.get(20) {
+        Some(1) => OtxStrictness::Strict,
+        _ => OtxStrictness::Lenient,
+    };
+    let activated = cobuild_entry_strict(&mut verifier, strictness)?;
+    if !activated || verifier.verified == 0 {
+        return Err(Error::AuthError);
     }
+    Ok(())
 }
@@ -0,0 +1,34 @@
+use ckb_transaction_cobuild::{cobuild_entry, error::Error, utils::load_args_array, Callback};
+use core::result::Result;
+use secp256k1::{schnorr::Signature, Message, Secp256k1, XOnlyPublicKey};
+
+/// Verifies 64-byte BIP-340 Schnorr seals against the x-only public key in
+/// the script args.
+struct SchnorrVerifier {
+    pubkey: XOnlyPublicKey,
+}
+
+impl Callback for SchnorrVerifier {
+    fn invoke(&mut self, seal: &[u8], signing_message_hash: &[u8; 32]) -> Result<(), Error> {
+        let signature = Signature::from_slice(seal).map_err(|_| Error::AuthError)?;
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(*signing_message_hash),
+                &self.pubkey,
+            )
+            .map_err(|_| Error::AuthError)
+    }
+}
+
+pub fn main() -> Result<(), Error> {
+    let pubkey = load_args_array::<32>()?;
+    let pubkey = XOnlyPublicKey::from_slice(&pubkey).map_err(|_| Error::AuthError)?;
+
+    // only cobuild witnesses are supported, there is no WitnessArgs fallback
+    let cobuild_activated = cobuild_entry(SchnorrVerifier { pubkey })?;
+    if !cobuild_activated {
+        return Err(Error::WrongWitnessLayout);
+    }
+    Ok(())
+}
@@ -1,21 +1,25 @@
+use alloc::vec::Vec;
 use ckb_std::{
     ckb_constants::Source,
-    ckb_types::prelude::*,
     high_level::{load_cell_data, load_script_hash},
     syscalls::SysError,
 };
-use ckb_transaction_cobuild::sighashall::fetch_message;
+use ckb_transaction_cobuild::{
+    lazy_reader::new_input_cell_data, sighashall::fetch_message_only, utils::ct_eq_32,
+};
 use core::result::Result;
 
 use crate::error::Error;
 
 pub fn main() -> Result<(), Error> {
     // fetch the message field of SighashAll and verify it
-    if let Ok(Some(message)) = fetch_message() {
+    if let Ok(Some(message)) = fetch_message_only() {
         let script_hash = load_script_hash()?;
-        for action in message.actions().into_iter() {
-            if action.script_hash().as_slice() == script_hash.as_slice()
-                && !verify_action_data(&action.data().raw_data())?
+        let actions = message.actions()?;
+        for index in 0..actions.len()? {
+            let action = actions.get(index)?;
+            if ct_eq_32(&action.script_hash()?, &script_hash)
+                && !verify_action_data(&Vec::<u8>::try_from(action.data()?)?)?
             {
                 return Err(Error::InvalidMessage);
             }
@@ -27,13 +31,14 @@ pub fn main() -> Result<(), Error> {
 
 // a simple verification logic for the demo
 fn verify_action_data(data: &[u8]) -> Result<bool, Error> {
+    // the data is read lazily, only its size is needed
     if data == b"MINT" {
         if let Err(SysError::IndexOutOfBound) = load_cell_data(0, Source::GroupInput) {
-            return Ok(!load_cell_data(0, Source::GroupOutput)?.is_empty());
+            return Ok(new_input_cell_data(0, Source::GroupOutput)?.size > 0);
         }
     } else if data == b"BURN" {
         if let Err(SysError::IndexOutOfBound) = load_cell_data(0, Source::GroupOutput) {
-            return Ok(!load_cell_data(0, Source::GroupInput)?.is_empty());
+            return Ok(new_input_cell_data(0, Source::GroupInput)?.size > 0);
         }
     }
     Ok(false)
@@ -1,4 +1,5 @@
 use ckb_std::error::SysError;
+use ckb_transaction_cobuild::error::LazyReaderError;
 
 /// Error
 #[repr(i8)]
@@ -23,3 +24,9 @@ impl From<SysError> for Error {
         }
     }
 }
+
+impl From<LazyReaderError> for Error {
+    fn from(_err: LazyReaderError) -> Self {
+        Self::Encoding
+    }
+}
@@ -1,18 +1,60 @@
 use super::{tx::*, *};
-use ckb_testtool::ckb_crypto::secp::{Generator, Message as SecpMessage};
+use ckb_std::{ckb_constants::Source, error::SysError};
+use ckb_testtool::ckb_crypto::secp::{Generator, Message as SecpMessage, Privkey};
 use ckb_testtool::{
     bytes::Bytes,
     ckb_error::Error,
-    ckb_hash::blake2b_256,
-    ckb_types::{core::TransactionBuilder, packed::*, prelude::*},
+    ckb_hash::{blake2b_256, new_blake2b},
+    ckb_types::{
+        core::{TransactionBuilder, TransactionView},
+        packed::*,
+        prelude::*,
+    },
     context::Context,
 };
-use ckb_transaction_cobuild::blake2b::new_otx_blake2b;
 use ckb_transaction_cobuild::schemas::{
-    basic::{Action, ActionVec, Message, Otx, OtxStart, ResolvedInputs, SealPair, SealPairVec},
+    basic::{
+        Action, ActionVec, Message, Otx, OtxStart, ResolvedInputs, SealPair, SealPairVec,
+        SighashAll, SighashAllOnly,
+    },
     top_level::{WitnessLayout, WitnessLayoutUnion},
 };
+use ckb_transaction_cobuild::{
+    all_signing_message_hashes,
+    builder::{self, OtxBuilder},
+    cobuild_entry_detailed, cobuild_entry_partial, cobuild_entry_with_limits,
+    cobuild_entry_with_message, cobuild_entry_with_script_match,
+    error::{Error as CobuildError, LazyReaderError},
+    host,
+    lazy_reader::{new_cursor_from_vec, shared_transaction, MAX_CACHE_SIZE},
+    mock::{cell_load_count, set_mock_transaction, MockTransaction, ScriptGroupType},
+    otx::{
+        otx_signing_message_hash_for, validate_otx_totals, verify_otx_group, OtxContext,
+        OtxDynamicConfigs, OtxSigningRange, OutputHashMask,
+    },
+    parse_witness_layout_bytes, parse_witness_layouts_with,
+    schemas2::{basic as lazy_basic, blockchain as lazy_blockchain, top_level as lazy_top_level},
+    seal::{Seal, SEAL_SCHEME_SCHNORR, SEAL_SCHEME_SECP256K1_RECOVERABLE},
+    sighashall::{
+        estimate_smh_cycles, fetch_message_only, fetch_seal_at, signing_message_hash_for,
+    },
+    test_utils,
+    utils::{
+        action_locations, assert_single_lock_instance, cache_group_script_hashes,
+        cache_script_hashes, cache_script_hashes_capped, cache_script_hashes_with_cell_deps,
+        check_message_self, count_script_cells, ct_eq, ct_eq_32, is_script_exist,
+        is_script_included, script_location, unsupported_witness_layout_id, validate_actions,
+        AnyWitnessLayout, ScriptLocation, ScriptType, WITNESS_LAYOUT_OTX, WITNESS_LAYOUT_OTX_START,
+        WITNESS_LAYOUT_SIGHASH_ALL, WITNESS_LAYOUT_SIGHASH_ALL_ONLY,
+    },
+    Callback, CobuildLimits, CobuildResult, CobuildState, MessageCallback, PartialCobuildResult,
+    ScriptMatch, MAX_OTX_COUNT,
+};
 use molecule::prelude::*;
+use rand::{thread_rng, RngCore};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::rc::Rc;
 
 const MAX_CYCLES: u64 = 10_000_000;
 
@@ -60,6 +102,20 @@ fn test_success_sighash_all_only() {
     println!("consume cycles: {}", cycles);
 }
 
+// Cycle benchmark of the signing message hash on a 20-input transaction.
+#[test]
+fn test_cycles_sighash_all_20_inputs() {
+    let mut witnesses = MessageWitnesses::new(vec![20], vec![]);
+    witnesses.set_with_action(0);
+
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
 #[test]
 fn test_failed_pubkey() {
     let others_witnesses = vec![];
@@ -80,6 +136,412 @@ fn test_failed_pubkey() {
     assert_script_error(err, 5); // return Error::AuthError
 }
 
+#[test]
+fn test_non_empty_witness_in_group() {
+    let mut witnesses = MessageWitnesses::new(vec![2], vec![]);
+    witnesses.set_with_action(0);
+
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+    // the second witness of the script group must be empty
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![
+            tx.witnesses().get(0).unwrap(),
+            Bytes::from(vec![1, 2, 3]).pack(),
+        ])
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 7); // return Error::WrongWitnessLayout
+}
+
+#[test]
+fn test_multiple_sighash_all() {
+    let mut witnesses = MessageWitnesses::new(vec![1, 1], vec![]);
+    witnesses.set_with_action(0);
+    witnesses.set_with_action(1);
+
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+
+    // only one SighashAll witness is allowed in a transaction
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 7); // return Error::WrongWitnessLayout
+}
+
+#[test]
+fn test_malformed_witness_layout() {
+    let witnesses = MessageWitnesses::new(vec![1], vec![]);
+    let (tx, _resolved_inputs, context) = gen_tx(&witnesses);
+
+    // the union id of SighashAllOnly followed by a truncated table
+    let mut malformed = 0xFF000002u32.to_le_bytes().to_vec();
+    malformed.extend_from_slice(&[1, 2, 3]);
+    assert!(matches!(
+        parse_witness_layout_bytes(&malformed),
+        Err(CobuildError::MoleculeEncodingAt {
+            field: "WitnessLayout",
+            cause,
+        }) if matches!(*cause, CobuildError::LazyReader(LazyReaderError::FieldCount(3)))
+    ));
+
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![Bytes::from(malformed).pack()])
+        .build();
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 22); // return Error::MoleculeEncodingAt
+}
+
+#[test]
+fn test_verification_error_kind() {
+    // a SighashAllOnly table whose header claims more bytes than present
+    let mut malformed = 0xFF000002u32.to_le_bytes().to_vec();
+    for word in [16u32, 8] {
+        malformed.extend_from_slice(&word.to_le_bytes());
+    }
+    let err: CobuildError = WitnessLayout::from_slice(&malformed)
+        .expect_err("malformed layout")
+        .into();
+    assert!(matches!(err, CobuildError::Verification(_)));
+    assert_eq!(err.code(), 25);
+    assert!(err.to_string().starts_with("molecule verification error"));
+}
+
+#[test]
+fn test_slice_length_error() {
+    fn signature(seal: &[u8]) -> Result<[u8; 64], CobuildError> {
+        Ok(seal.try_into()?)
+    }
+    assert_eq!(signature(&[7u8; 64]).unwrap(), [7u8; 64]);
+    let err = signature(&[7u8; 65]).unwrap_err();
+    assert!(matches!(err, CobuildError::SliceLength));
+    assert_eq!(err.code(), 26);
+}
+
+#[test]
+fn test_witness_layout_ids() {
+    let layouts = [
+        (
+            WITNESS_LAYOUT_SIGHASH_ALL,
+            WitnessLayoutUnion::SighashAll(Default::default()),
+        ),
+        (
+            WITNESS_LAYOUT_SIGHASH_ALL_ONLY,
+            WitnessLayoutUnion::SighashAllOnly(Default::default()),
+        ),
+        (
+            WITNESS_LAYOUT_OTX,
+            WitnessLayoutUnion::Otx(Default::default()),
+        ),
+        (
+            WITNESS_LAYOUT_OTX_START,
+            WitnessLayoutUnion::OtxStart(Default::default()),
+        ),
+    ];
+    for (id, union) in layouts {
+        let witness = WitnessLayout::new_builder().set(union).build().as_bytes();
+        assert_eq!(witness[..4], id.to_le_bytes());
+        let layout =
+            lazy_top_level::WitnessLayout::try_from(new_cursor_from_vec(witness.to_vec())).unwrap();
+        let matched = match layout {
+            lazy_top_level::WitnessLayout::SighashAll(_) => WITNESS_LAYOUT_SIGHASH_ALL,
+            lazy_top_level::WitnessLayout::SighashAllOnly(_) => WITNESS_LAYOUT_SIGHASH_ALL_ONLY,
+            lazy_top_level::WitnessLayout::Otx(_) => WITNESS_LAYOUT_OTX,
+            lazy_top_level::WitnessLayout::OtxStart(_) => WITNESS_LAYOUT_OTX_START,
+        };
+        assert_eq!(matched, id);
+    }
+}
+
+#[test]
+fn test_parse_witness_layouts_compatible() {
+    // a SighashAllOnly table extended with a trailing empty Bytes field
+    let mut extended = 0xFF000002u32.to_le_bytes().to_vec();
+    for word in [20u32, 12, 16, 0, 0] {
+        extended.extend_from_slice(&word.to_le_bytes());
+    }
+    let tx = TransactionBuilder::default()
+        .witness(Bytes::from(extended).pack())
+        .build();
+    let lazy_tx =
+        lazy_blockchain::Transaction::from(new_cursor_from_vec(tx.data().as_slice().to_vec()));
+
+    assert!(parse_witness_layouts_with(&lazy_tx, false).is_err());
+    let (witness_layouts, activated) = parse_witness_layouts_with(&lazy_tx, true).unwrap();
+    assert!(activated);
+    assert!(witness_layouts[0].is_some());
+}
+
+#[test]
+fn test_group_without_witness() {
+    let mut witnesses = MessageWitnesses::new(vec![1, 1], vec![]);
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+
+    // the second group has no witness at all
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![tx.witnesses().get(0).unwrap()])
+        .build();
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 7); // return Error::WrongWitnessLayout
+}
+
+// The lock demo runs cobuild_auto_entry. Used as the type script of an
+// output, it only checks the message, since there is no input locked by it.
+#[test]
+fn test_auto_entry_type_role() {
+    for (script_type, expected) in [(2u8, None), (0u8, Some(16))] {
+        let mut witnesses = MessageWitnesses::new(vec![1], vec![]);
+        let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+
+        let type_script = resolved_inputs
+            .outputs()
+            .get(0)
+            .unwrap()
+            .lock()
+            .as_builder()
+            .args(Bytes::from(vec![0u8; 20]).pack())
+            .build();
+        let action = Action::new_builder()
+            .script_hash(type_script.calc_script_hash())
+            .script_type(script_type.into())
+            .build();
+        witnesses.message_data[0].action = Some(
+            Message::new_builder()
+                .actions(ActionVec::new_builder().push(action).build())
+                .build(),
+        );
+        let tx = tx
+            .as_advanced_builder()
+            .output(
+                CellOutput::new_builder()
+                    .capacity(500u64.pack())
+                    .lock(type_script.clone())
+                    .type_(Some(type_script).pack())
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .build();
+        let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+
+        let result = context.verify_tx(&tx, MAX_CYCLES);
+        if let Some(code) = expected {
+            // the action claims an input lock, return Error::ScriptHashAbsent
+            assert_script_error(result.expect_err("fail verification"), code);
+        } else {
+            result.expect("pass verification");
+        }
+    }
+}
+
+#[test]
+fn test_lock_args_too_short() {
+    let mut context = Context::default();
+    let out_point =
+        context.deploy_cell(Loader::default().load_binary("transaction-cobuild-lock-demo"));
+    // the lock demo expects a 20-byte pubkey hash
+    let lock_script = context
+        .build_script(&out_point, Bytes::from(vec![0u8; 10]))
+        .expect("script");
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let tx = TransactionBuilder::default()
+        .input(
+            CellInput::new_builder()
+                .previous_output(input_out_point)
+                .build(),
+        )
+        .output(
+            CellOutput::new_builder()
+                .capacity(500u64.pack())
+                .lock(lock_script)
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build();
+    let tx = context.complete_tx(tx);
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 21); // return Error::DataLength
+}
+
+#[test]
+fn test_success_test_utils_message_witnesses() {
+    let witnesses = MessageWitnesses::new(vec![2, 1], vec![]);
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+
+    let mut message_witnesses = test_utils::MessageWitnesses::new(vec![vec![0, 1], vec![2]]);
+    message_witnesses.set_with_action(1, Message::new_builder().build());
+    let mut sign = |group: usize, signing_message_hash: &[u8; 32]| -> Bytes {
+        let signature = witnesses.message_data[group]
+            .privkey
+            .sign_recoverable(&SecpMessage::from_slice(signing_message_hash).unwrap())
+            .expect("sign")
+            .serialize();
+        Bytes::from(signature)
+    };
+    let signed_tx = message_witnesses
+        .sign(&tx.data(), &resolved_inputs, &mut sign)
+        .unwrap();
+    context
+        .verify_tx(&signed_tx.into_view(), MAX_CYCLES)
+        .expect("pass verification");
+
+    // an extra witness changes the signing message hash, so sign again
+    message_witnesses.others.push(Bytes::from(vec![42]));
+    let signed_tx = message_witnesses
+        .sign(&tx.data(), &resolved_inputs, &mut sign)
+        .unwrap();
+    context
+        .verify_tx(&signed_tx.into_view(), MAX_CYCLES)
+        .expect("pass verification");
+}
+
+// Only the signing message hash reads the content of the witnesses beyond
+// the inputs count, so the cycles they add are compared with the estimate.
+// Cycles of the transaction-cobuild-cycles-demo operation `op` on a
+// transaction with `inputs` input cells of `input_data` bytes of data each,
+// followed by `witnesses` witnesses of `witness_size` bytes beyond the
+// inputs count, minus the cycles of the same transaction doing nothing.
+fn measure_operation_cycles(
+    op: u8,
+    inputs: usize,
+    input_data: usize,
+    witnesses: usize,
+    witness_size: usize,
+) -> u64 {
+    let cycles = |op: u8| {
+        let mut context = Context::default();
+        let out_point =
+            context.deploy_cell(Loader::default().load_binary("transaction-cobuild-cycles-demo"));
+        let lock_script = context
+            .build_script(&out_point, Bytes::from(vec![op]))
+            .expect("script");
+        let mut builder = TransactionBuilder::default();
+        for _ in 0..inputs {
+            let input_out_point = context.create_cell(
+                CellOutput::new_builder()
+                    .capacity(1_000_000u64.pack())
+                    .lock(lock_script.clone())
+                    .build(),
+                Bytes::from(vec![7u8; input_data]),
+            );
+            builder = builder.input(
+                CellInput::new_builder()
+                    .previous_output(input_out_point)
+                    .build(),
+            );
+        }
+        builder = builder
+            .output(
+                CellOutput::new_builder()
+                    .capacity(500u64.pack())
+                    .lock(lock_script)
+                    .build(),
+            )
+            .output_data(Bytes::new().pack());
+        for _ in 0..inputs {
+            builder = builder.witness(Bytes::new().pack());
+        }
+        for _ in 0..witnesses {
+            builder = builder.witness(Bytes::from(vec![9u8; witness_size]).pack());
+        }
+        let tx = context.complete_tx(builder.build());
+        context.verify_tx(&tx, u64::MAX).expect("pass verification")
+    };
+    cycles(op) - cycles(0)
+}
+
+#[test]
+fn test_estimate_smh_cycles() {
+    for (inputs, input_data, witnesses, witness_size) in [
+        (1, 0, 0, 0),
+        (2, 0, 0, 0),
+        (21, 0, 0, 0),
+        (1, 0, 1, 0),
+        (1, 0, 21, 0),
+        (1, 0, 1, 64 * 1024),
+        (1, 64 * 1024, 0, 0),
+        (10, 1000, 10, 1000),
+        (11, 0, 0, 0),
+        (1, 0, 11, 0),
+        (1, 0, 1, 1024),
+        (1, 1024, 0, 0),
+    ] {
+        let measured = measure_operation_cycles(1, inputs, input_data, witnesses, witness_size);
+        let estimated = estimate_smh_cycles(
+            inputs,
+            inputs * input_data,
+            witnesses,
+            witnesses * witness_size,
+        );
+        println!(
+            "inputs {} data {} witnesses {} size {}: measured {} cycles, estimated {}",
+            inputs, input_data, witnesses, witness_size, measured, estimated
+        );
+        // the bound stated at the coefficients of estimate_smh_cycles
+        assert!(measured.abs_diff(estimated) * 20 <= measured);
+    }
+}
+
+#[test]
+fn test_shared_transaction_cycles() {
+    // fetch_message and generate_signing_message_hash, with the transaction
+    // reader shared or set up again by each of them
+    let shared = measure_operation_cycles(2, 4, 0, 4, 1024);
+    let unshared = measure_operation_cycles(3, 4, 0, 4, 1024);
+    println!("shared reader {} cycles, unshared {}", shared, unshared);
+    assert!(shared < unshared);
+}
+
+#[test]
+fn test_cell_cache_cycles() {
+    // two signing message hashes over the same inputs, the second one reuses
+    // the cursors memoized by the first one: with DEFAULT_CELL_CACHE_LIMIT,
+    // those of 16 inputs, while the 8 others are set up again
+    for input_data in [0, 1024, 16 * 1024] {
+        let cached = measure_operation_cycles(4, 24, input_data, 0, 0);
+        let uncached = measure_operation_cycles(5, 24, input_data, 0, 0);
+        println!(
+            "24 inputs of {} bytes: cached {} cycles, uncached {}",
+            input_data, cached, uncached
+        );
+        assert!(cached < uncached);
+    }
+}
+
+#[test]
+fn test_empty_seal() {
+    let mut witnesses = MessageWitnesses::new(vec![1], vec![]);
+    witnesses.set_with_action(0);
+    witnesses.message_data[0].sign = Some(vec![]);
+
+    let (tx, _resolved_inputs, context) = gen_tx(&witnesses);
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 15); // return Error::NoSealFound
+}
+
 #[test]
 fn test_type_script() {
     let mut witnesses = MessageWitnesses::new(vec![3, 1, 2], vec![]);
@@ -100,6 +562,274 @@ fn test_type_script() {
     println!("consume cycles: {}", cycles);
 }
 
+// The cells deployed by ckb-testtool carry a type id script, so the type
+// script of a cell dep can be named by an action of a lock message.
+#[test]
+fn test_cell_dep_action_in_lock_flow() {
+    let mut witnesses = MessageWitnesses::new(vec![1], vec![]);
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let cell_dep = tx.cell_deps().get(0).unwrap();
+    let (cell_dep_output, _) = context.get_cell(&cell_dep.out_point()).unwrap();
+    let cell_dep_type = cell_dep_output.type_().to_opt().unwrap();
+
+    for (script_type, expected) in [(6u8, None), (2, Some(16))] {
+        let action = Action::new_builder()
+            .script_hash(cell_dep_type.calc_script_hash())
+            .script_type(script_type.into())
+            .build();
+        witnesses.message_data[0].action = Some(
+            Message::new_builder()
+                .actions(ActionVec::new_builder().push(action).build())
+                .build(),
+        );
+        let tx = sign_tx(&mut witnesses, tx.clone(), resolved_inputs.clone());
+
+        let result = context.verify_tx(&tx, MAX_CYCLES);
+        if let Some(code) = expected {
+            // the script is no output type, return Error::ScriptHashAbsent
+            assert_script_error(result.expect_err("fail verification"), code);
+        } else {
+            result.expect("pass verification");
+        }
+    }
+}
+
+// Signs the transaction the secp256k1_blake160_sighash_all way, for a script
+// group with a single input and no witnesses beyond the inputs count.
+fn legacy_witness(privkey: &Privkey, tx: &TransactionView) -> Bytes {
+    let zero_witness = WitnessArgs::new_builder()
+        .lock(Some(Bytes::from(vec![0u8; 65])).pack())
+        .build();
+    let mut hasher = new_blake2b();
+    hasher.update(tx.hash().as_slice());
+    hasher.update(&(zero_witness.as_slice().len() as u64).to_le_bytes());
+    hasher.update(zero_witness.as_slice());
+    let mut signing_message_hash = [0u8; 32];
+    hasher.finalize(&mut signing_message_hash);
+    let signature = privkey
+        .sign_recoverable(&SecpMessage::from_slice(&signing_message_hash).unwrap())
+        .expect("sign")
+        .serialize();
+
+    zero_witness
+        .as_builder()
+        .lock(Some(Bytes::from(signature)).pack())
+        .build()
+        .as_bytes()
+}
+
+// The type script is only on an input cell, its data is read from
+// `Source::GroupInput` by the type demo.
+#[test]
+fn test_type_script_burn() {
+    let mut witnesses = MessageWitnesses::new(vec![1], vec![]);
+    let (tx, resolved_inputs, mut context) = gen_tx(&witnesses);
+    let type_script = tx.outputs().get(1).unwrap().type_().to_opt().unwrap();
+
+    // an input cell of the same lock, carrying the type script
+    let input_cell = resolved_inputs
+        .outputs()
+        .get(0)
+        .unwrap()
+        .as_builder()
+        .type_(Some(type_script.clone()).pack())
+        .build();
+    let input_data = Bytes::from(b"TOKEN".to_vec());
+    let input_out_point = context.create_cell(input_cell.clone(), input_data.clone());
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(
+            resolved_inputs
+                .outputs()
+                .as_builder()
+                .push(input_cell)
+                .build(),
+        )
+        .outputs_data(
+            resolved_inputs
+                .outputs_data()
+                .as_builder()
+                .push(input_data.pack())
+                .build(),
+        )
+        .build();
+    let outputs: Vec<CellOutput> = tx
+        .outputs()
+        .into_iter()
+        .map(|output| output.as_builder().type_(None::<Script>.pack()).build())
+        .collect();
+    let tx = tx
+        .as_advanced_builder()
+        .input(
+            CellInput::new_builder()
+                .previous_output(input_out_point)
+                .build(),
+        )
+        .set_outputs(outputs)
+        .build();
+
+    let actions = vec![Action::new_builder()
+        .script_hash(type_script.calc_script_hash())
+        .script_type(1u8.into()) // input type
+        .data(Bytes::from(b"BURN".to_vec()).pack())
+        .build()];
+    let msg = Message::new_builder().actions(ActionVec::new_builder().set(actions).build());
+    witnesses.message_data[0].action = Some(msg.build());
+    witnesses.message_data[0].group_size = 2;
+
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_success_legacy_witness_args() {
+    let witnesses = MessageWitnesses::new(vec![1], vec![]);
+    let (tx, _resolved_inputs, context) = gen_tx(&witnesses);
+
+    let witness = legacy_witness(&witnesses.message_data[0].privkey, &tx);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![witness.pack()])
+        .build();
+
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+// The first group uses cobuild and the second one WitnessArgs. Cobuild is
+// activated for the transaction, but the lock decides per group.
+#[test]
+fn test_success_mixed_cobuild_and_witness_args() {
+    let mut witnesses = MessageWitnesses::new(vec![1, 1], vec![]);
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+
+    let mut tx_witnesses: Vec<Bytes> = tx.witnesses().into_iter().map(|w| w.unpack()).collect();
+    tx_witnesses[1] = legacy_witness(&witnesses.message_data[1].privkey, &tx);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(tx_witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_mixed_cobuild_and_witness_args_wrong_signer() {
+    let mut witnesses = MessageWitnesses::new(vec![1, 1], vec![]);
+    let (tx, resolved_inputs, context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs);
+
+    // the WitnessArgs of the second group is signed by the first group's key
+    let mut tx_witnesses: Vec<Bytes> = tx.witnesses().into_iter().map(|w| w.unpack()).collect();
+    tx_witnesses[1] = legacy_witness(&witnesses.message_data[0].privkey, &tx);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(tx_witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+}
+
+#[test]
+fn test_signing_message_hash_for_matches_host() {
+    let mut witnesses = MessageWitnesses::new(vec![3, 1, 2], vec![]);
+    witnesses.set_with_action(1);
+    let (tx, resolved_inputs, _context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs.clone());
+    let message = witnesses.get_action();
+
+    let expected = host::signing_message_hash(&message, &tx.data(), &resolved_inputs).unwrap();
+
+    let lazy_tx =
+        lazy_blockchain::Transaction::from(new_cursor_from_vec(tx.data().as_slice().to_vec()));
+    let lazy_resolved_inputs =
+        lazy_basic::ResolvedInputs::from(new_cursor_from_vec(resolved_inputs.as_slice().to_vec()));
+    let lazy_message =
+        message.map(|m| lazy_basic::Message::from(new_cursor_from_vec(m.as_slice().to_vec())));
+    let actual = signing_message_hash_for(
+        &lazy_tx,
+        tx.hash().as_slice().try_into().unwrap(),
+        &lazy_message,
+        &lazy_resolved_inputs,
+    )
+    .unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_otx_signing_message_hash_for_matches_host() {
+    let (_context, _privkey, _lock_script, tx, resolved_inputs) = gen_otx_tx(3, 2);
+    let message = Message::new_builder().build();
+    let signing_range = || {
+        OtxSigningRange::default()
+            .with_inputs(1, 2)
+            .with_outputs(0, 1)
+            .with_cell_deps(0, 1)
+    };
+    let expected = host::otx_signing_message_hash(
+        &message,
+        &tx.data().raw(),
+        &resolved_inputs,
+        &signing_range(),
+    )
+    .unwrap();
+
+    let lazy_raw_tx = lazy_blockchain::RawTransaction::from(new_cursor_from_vec(
+        tx.data().raw().as_slice().to_vec(),
+    ));
+    let lazy_resolved_inputs =
+        lazy_basic::ResolvedInputs::from(new_cursor_from_vec(resolved_inputs.as_slice().to_vec()));
+    let lazy_message = lazy_basic::Message::from(new_cursor_from_vec(message.as_slice().to_vec()));
+    let actual = otx_signing_message_hash_for(
+        &lazy_raw_tx,
+        lazy_message.clone(),
+        signing_range(),
+        &lazy_resolved_inputs,
+    )
+    .unwrap();
+    assert_eq!(actual, expected);
+
+    // ResolvedInputs doesn't provide the data of cell deps
+    assert!(matches!(
+        otx_signing_message_hash_for(
+            &lazy_raw_tx,
+            lazy_message.clone(),
+            signing_range().with_cell_dep_data(true),
+            &lazy_resolved_inputs,
+        ),
+        Err(CobuildError::CellReadFailed { index: 0, .. })
+    ));
+    // the first input of the range can't be resolved
+    let empty_resolved_inputs = lazy_basic::ResolvedInputs::from(new_cursor_from_vec(
+        ResolvedInputs::default().as_slice().to_vec(),
+    ));
+    let err = otx_signing_message_hash_for(
+        &lazy_raw_tx,
+        lazy_message,
+        signing_range(),
+        &empty_resolved_inputs,
+    )
+    .unwrap_err();
+    // the lazy reader error is kept as the cause
+    assert!(matches!(
+        &err,
+        CobuildError::CellReadFailed { index: 1, cause, .. }
+            if matches!(**cause, CobuildError::LazyReader(LazyReaderError::OutOfBound(..)))
+    ));
+    assert_eq!(err.code(), 23);
+}
+
 #[test]
 fn test_success_otx() {
     // deploy contract
@@ -209,37 +939,3038 @@ fn generate_otx_signing_message_hash(
     otx: &RawTransaction,
     resolved_inputs: &ResolvedInputs,
 ) -> [u8; 32] {
-    let mut hasher = new_otx_blake2b();
-    // message
-    hasher.update(message.as_slice());
-    // otx inputs
-    let inputs_len = otx.inputs().len();
-    debug_assert!(inputs_len == resolved_inputs.outputs().len());
-    debug_assert!(inputs_len == resolved_inputs.outputs_data().len());
-    hasher.update(&(inputs_len as u32).to_le_bytes());
-    for i in 0..inputs_len {
-        hasher.update(otx.inputs().get(i).unwrap().as_slice());
-        let input_cell = resolved_inputs.outputs().get(i).unwrap();
-        hasher.update(input_cell.as_slice());
-        let input_cell_data = resolved_inputs.outputs_data().get(i).unwrap();
-        hasher.update(&(input_cell_data.len() as u32).to_le_bytes());
-        hasher.update(&input_cell_data.raw_data());
+    // in this unit test, we don't sign any cell and header deps for otx
+    let signing_range = OtxSigningRange::default()
+        .with_inputs(0, otx.inputs().len() as u32)
+        .with_outputs(0, otx.outputs().len() as u32);
+    host::otx_signing_message_hash(message, otx, resolved_inputs, &signing_range).unwrap()
+}
+
+// Deploys the OTX lock demo, then builds a transaction with `inputs_count`
+// inputs and `outputs_count` outputs, all locked by it.
+fn gen_otx_tx(
+    inputs_count: usize,
+    outputs_count: usize,
+) -> (Context, Privkey, Script, TransactionView, ResolvedInputs) {
+    gen_otx_tx_with_flags(inputs_count, outputs_count, &[])
+}
+
+// Same as `gen_otx_tx`, with `flags` appended to the lock args after the
+// pubkey hash.
+fn gen_otx_tx_with_flags(
+    inputs_count: usize,
+    outputs_count: usize,
+    flags: &[u8],
+) -> (Context, Privkey, Script, TransactionView, ResolvedInputs) {
+    let mut context = Context::default();
+    let loader = Loader::default();
+    let otx_out_point =
+        context.deploy_cell(loader.load_binary("transaction-cobuild-otx-lock-demo"));
+
+    let privkey = Generator::random_privkey();
+    let pubkey_hash: [u8; 20] = blake2b_256(privkey.pubkey().unwrap().serialize().as_slice())[..20]
+        .try_into()
+        .unwrap();
+    let lock_script = context
+        .build_script(&otx_out_point, [&pubkey_hash[..], flags].concat().into())
+        .expect("script");
+
+    let mut tx = TransactionBuilder::default();
+    let mut resolved_outputs = CellOutputVec::new_builder();
+    let mut resolved_outputs_data = BytesVec::new_builder();
+    for _ in 0..inputs_count {
+        let input_cell = CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build();
+        let input_out_point = context.create_cell(input_cell.clone(), Default::default());
+        resolved_outputs = resolved_outputs.push(input_cell);
+        resolved_outputs_data = resolved_outputs_data.push(Default::default());
+        tx = tx.input(
+            CellInput::new_builder()
+                .previous_output(input_out_point)
+                .build(),
+        );
     }
-    // otx outputs
-    let outputs_len = otx.outputs().len();
-    debug_assert!(outputs_len == otx.outputs_data().len());
-    hasher.update(&(outputs_len as u32).to_le_bytes());
-    for i in 0..outputs_len {
-        hasher.update(otx.outputs().get(i).unwrap().as_slice());
-        hasher.update(otx.outputs_data().get(i).unwrap().as_slice());
+    for _ in 0..outputs_count {
+        tx = tx
+            .output(
+                CellOutput::new_builder()
+                    .capacity(500u64.pack())
+                    .lock(lock_script.clone())
+                    .build(),
+            )
+            .output_data(Bytes::new().pack());
     }
-    // otx deps (in this unit test, we don't sign any cell and header deps for otx)
-    hasher.update(&0u32.to_le_bytes());
-    hasher.update(&0u32.to_le_bytes());
+    let tx = context.complete_tx(tx.build());
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(resolved_outputs.build())
+        .outputs_data(resolved_outputs_data.build())
+        .build();
+
+    (context, privkey, lock_script, tx, resolved_inputs)
+}
 
-    let mut result = [0u8; 32];
-    hasher.finalize(&mut result);
-    result
+// Extracts the inputs and outputs covered by an OTX signing range, so
+// `generate_otx_signing_message_hash` can be used on part of a transaction.
+fn otx_range(
+    raw_tx: &RawTransaction,
+    resolved_inputs: &ResolvedInputs,
+    inputs: Range<usize>,
+    outputs: Range<usize>,
+) -> (RawTransaction, ResolvedInputs) {
+    let otx = RawTransaction::new_builder()
+        .inputs(
+            CellInputVec::new_builder()
+                .set(
+                    inputs
+                        .clone()
+                        .map(|i| raw_tx.inputs().get(i).unwrap())
+                        .collect(),
+                )
+                .build(),
+        )
+        .outputs(
+            CellOutputVec::new_builder()
+                .set(
+                    outputs
+                        .clone()
+                        .map(|i| raw_tx.outputs().get(i).unwrap())
+                        .collect(),
+                )
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(
+                    outputs
+                        .map(|i| raw_tx.outputs_data().get(i).unwrap())
+                        .collect(),
+                )
+                .build(),
+        )
+        .build();
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .set(
+                    inputs
+                        .clone()
+                        .map(|i| resolved_inputs.outputs().get(i).unwrap())
+                        .collect(),
+                )
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(
+                    inputs
+                        .map(|i| resolved_inputs.outputs_data().get(i).unwrap())
+                        .collect(),
+                )
+                .build(),
+        )
+        .build();
+    (otx, resolved_inputs)
+}
+
+fn otx_seal_pair(
+    privkey: &Privkey,
+    lock_script: &Script,
+    signing_message_hash: &[u8; 32],
+) -> SealPair {
+    let signature = privkey
+        .sign_recoverable(&SecpMessage::from_slice(signing_message_hash).unwrap())
+        .unwrap()
+        .serialize();
+    SealPair::new_builder()
+        .script_hash(lock_script.calc_script_hash())
+        .seal(Bytes::from(signature).pack())
+        .build()
+}
+
+// `starts` are the start indices of inputs, outputs, cell deps and header deps
+fn otx_start_witness(starts: [u32; 4]) -> Bytes {
+    builder::otx_start_witness(starts[0], starts[1], starts[2], starts[3])
+}
+
+// `fixed` and `dynamic` are the counts of inputs, outputs, cell deps and
+// header deps
+fn otx_witness(
+    flag: u8,
+    fixed: [u32; 4],
+    dynamic: [u32; 4],
+    message: Message,
+    seals: Vec<SealPair>,
+) -> Bytes {
+    let otx = Otx::new_builder()
+        .flag(flag.into())
+        .fixed_input_cells(fixed[0].pack())
+        .fixed_output_cells(fixed[1].pack())
+        .fixed_cell_deps(fixed[2].pack())
+        .fixed_header_deps(fixed[3].pack())
+        .dynamic_input_cells(dynamic[0].pack())
+        .dynamic_output_cells(dynamic[1].pack())
+        .dynamic_cell_deps(dynamic[2].pack())
+        .dynamic_header_deps(dynamic[3].pack())
+        .message(message)
+        .seals(SealPairVec::new_builder().set(seals).build())
+        .build();
+    WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::Otx(otx))
+        .build()
+        .as_bytes()
+}
+
+#[test]
+fn test_success_otx_dynamic_outputs() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 2);
+    let message = Message::new_builder().build();
+
+    // input 0 and output 0 are fixed, input 1 and output 1 are dynamic
+    let (fixed_otx, fixed_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 0..1, 0..1);
+    let fixed_smh = generate_otx_signing_message_hash(&message, &fixed_otx, &fixed_inputs);
+    // the dynamic signing range covers both fixed and dynamic outputs
+    let dynamic_smh =
+        generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0b0000_0011,
+            [1, 1, 0, 0],
+            [1, 1, 0, 0],
+            message,
+            vec![
+                otx_seal_pair(&privkey, &lock_script, &fixed_smh),
+                otx_seal_pair(&privkey, &lock_script, &dynamic_smh),
+            ],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+// The lock isn't in the inputs of the OTX, but an output using it is appended
+// dynamically, so the OTX must also carry its seal over the dynamic range.
+#[test]
+fn test_otx_dynamic_outputs_only() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 2);
+    let message = Message::new_builder().build();
+    let (dynamic_otx, dynamic_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 0..0, 0..2);
+    let dynamic_smh = generate_otx_signing_message_hash(&message, &dynamic_otx, &dynamic_inputs);
+
+    let sighash_all_only = |seal: Vec<u8>| {
+        WitnessLayout::new_builder()
+            .set(WitnessLayoutUnion::SighashAllOnly(
+                SighashAllOnly::new_builder()
+                    .seal(Bytes::from(seal).pack())
+                    .build(),
+            ))
+            .build()
+            .as_bytes()
+    };
+    // input 0 is after the OTX, which only has outputs: output 0 is fixed,
+    // output 1 is dynamic, unless `fixed_outputs` is 2
+    let sign = |fixed_outputs: u32, seals: Vec<SealPair>| {
+        let mut witnesses = [
+            sighash_all_only(vec![]),
+            otx_start_witness([0, 0, 0, 0]),
+            otx_witness(
+                0b0000_0010,
+                [0, fixed_outputs, 0, 0],
+                [0, 2 - fixed_outputs, 0, 0],
+                message.clone(),
+                seals,
+            ),
+        ];
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+            .build();
+        let smh = host::signing_message_hash(&None, &tx.data(), &resolved_inputs).unwrap();
+        let signature = privkey
+            .sign_recoverable(&SecpMessage::from_slice(&smh).unwrap())
+            .unwrap()
+            .serialize();
+        witnesses[0] = sighash_all_only(signature);
+        tx.as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+            .build()
+    };
+
+    let err = context
+        .verify_tx(&sign(1, vec![]), MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 15); // return Error::NoSealFound
+
+    context
+        .verify_tx(
+            &sign(1, vec![otx_seal_pair(&privkey, &lock_script, &dynamic_smh)]),
+            MAX_CYCLES,
+        )
+        .expect("pass verification");
+
+    // outputs in the fixed part don't need the seal of the lock
+    context
+        .verify_tx(&sign(2, vec![]), MAX_CYCLES)
+        .expect("pass verification");
+}
+
+#[test]
+fn test_otx_fixed_inputs_exceed_inputs() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    // claims 2 fixed inputs while the transaction has only 1
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0,
+            [2, 1, 0, 0],
+            [0, 0, 0, 0],
+            message,
+            vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 10); // return Error::WrongCount
+}
+
+#[test]
+fn test_otx_start_exceeds_inputs() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    // start_input_cell points past the only input
+    let witnesses = vec![
+        otx_start_witness([2, 0, 0, 0]),
+        otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message,
+            vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 8); // return Error::WrongOtxStart
+}
+
+// Guards the OTX signing message hash format: any change in the framing of
+// the hashed fields changes this hash.
+#[test]
+fn test_otx_signing_message_hash_golden() {
+    let raw_tx = RawTransaction::new_builder()
+        .cell_deps(vec![CellDep::default()].pack())
+        .header_deps(vec![[0x11u8; 32].pack(), [0x22u8; 32].pack()].pack())
+        .build();
+    let message = Message::new_builder().build();
+    let signing_range = OtxSigningRange::default()
+        .with_cell_deps(0, 1)
+        .with_header_deps(0, 2);
+    let smh = host::otx_signing_message_hash(
+        &message,
+        &raw_tx,
+        &ResolvedInputs::default(),
+        &signing_range,
+    )
+    .unwrap();
+    assert_eq!(
+        smh,
+        [
+            0x00, 0x30, 0x0f, 0x16, 0xbb, 0x31, 0xb6, 0x05, 0xec, 0x0c, 0x9b, 0x3a, 0xc8, 0x89,
+            0xf0, 0xc7, 0x7a, 0xd7, 0xc1, 0x9d, 0x77, 0x64, 0x3f, 0x55, 0x45, 0x1b, 0xd9, 0xbc,
+            0x8d, 0xc0, 0x56, 0x64
+        ]
+    );
+}
+
+// Many OTXs carry no actions. The empty message is still hashed, so the
+// hash must stay the same across versions.
+#[test]
+fn test_otx_empty_message_golden() {
+    let message = Message::new_builder().build();
+    assert_eq!(message.as_slice(), [12, 0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0]);
+    let smh = host::otx_signing_message_hash(
+        &message,
+        &RawTransaction::default(),
+        &ResolvedInputs::default(),
+        &OtxSigningRange::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        smh,
+        [
+            0xfc, 0xe0, 0x7f, 0xe7, 0x75, 0xf7, 0x90, 0x81, 0x4e, 0x10, 0xd9, 0x98, 0xe6, 0x8d,
+            0x5a, 0xd5, 0x59, 0x4d, 0xb9, 0x0d, 0xa0, 0x08, 0x77, 0xbe, 0x7f, 0xe9, 0xa2, 0x87,
+            0x59, 0x13, 0xf0, 0x91
+        ]
+    );
+}
+
+// The message is hashed before the transaction hash. The expected values are
+// computed independently with blake2b.
+#[test]
+fn test_signing_message_hash_order_golden() {
+    let tx = Transaction::new_builder()
+        .witnesses(vec![Bytes::from(vec![0x2a]).pack()].pack())
+        .build();
+    assert_eq!(
+        tx.calc_tx_hash().as_slice(),
+        [
+            0xf2, 0xe7, 0xa5, 0x36, 0x2e, 0x21, 0x7e, 0xd4, 0xd7, 0xf9, 0x85, 0xaf, 0x71, 0xb6,
+            0x54, 0xca, 0xfa, 0xdd, 0x0c, 0x3b, 0x6d, 0x6a, 0x6c, 0x13, 0xf1, 0xb1, 0x3a, 0x6b,
+            0xfd, 0x0d, 0x3d, 0x14
+        ]
+    );
+    let smh = host::signing_message_hash(
+        &Some(Message::new_builder().build()),
+        &tx,
+        &ResolvedInputs::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        smh,
+        [
+            0x9d, 0xb0, 0x28, 0xfa, 0xd9, 0xe2, 0x68, 0x04, 0x25, 0x5b, 0x2a, 0x17, 0xdb, 0x2b,
+            0xd3, 0x12, 0xc5, 0xc9, 0x73, 0xf3, 0x6f, 0x7c, 0xfe, 0x01, 0x7c, 0x69, 0xa3, 0x69,
+            0x24, 0xfb, 0x73, 0x74
+        ]
+    );
+}
+
+// Witnesses beyond the inputs count are hashed with a u32 length prefix,
+// the ones below it are not hashed at all.
+#[test]
+fn test_signing_message_hash_witnesses_golden() {
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .push(CellOutput::default())
+                .build(),
+        )
+        .outputs_data(BytesVec::new_builder().push(Default::default()).build())
+        .build();
+    let message = Some(Message::new_builder().build());
+    let tx_with_witnesses = |witnesses: Vec<Vec<u8>>| {
+        Transaction::new_builder()
+            .raw(
+                RawTransaction::new_builder()
+                    .inputs(vec![CellInput::default()].pack())
+                    .build(),
+            )
+            .witnesses(
+                witnesses
+                    .into_iter()
+                    .map(|w| Bytes::from(w).pack())
+                    .collect::<Vec<_>>()
+                    .pack(),
+            )
+            .build()
+    };
+
+    let smh = host::signing_message_hash(
+        &message,
+        &tx_with_witnesses(vec![vec![1, 2, 3], vec![0xaa], vec![]]),
+        &resolved_inputs,
+    )
+    .unwrap();
+    assert_eq!(
+        smh,
+        [
+            0x80, 0xe5, 0xcb, 0x99, 0x88, 0xc5, 0x94, 0xd7, 0x17, 0x38, 0xef, 0xf4, 0x27, 0xd6,
+            0x36, 0x47, 0x28, 0xcb, 0xf6, 0x74, 0xb8, 0xb6, 0x06, 0x98, 0x92, 0x8b, 0x4a, 0x79,
+            0xfc, 0x0c, 0xc2, 0xc7
+        ]
+    );
+
+    // the witness of the input isn't hashed, the trailing ones are
+    let other_seal = host::signing_message_hash(
+        &message,
+        &tx_with_witnesses(vec![vec![4, 5, 6], vec![0xaa], vec![]]),
+        &resolved_inputs,
+    )
+    .unwrap();
+    assert_eq!(other_seal, smh);
+    let no_empty_witness = host::signing_message_hash(
+        &message,
+        &tx_with_witnesses(vec![vec![1, 2, 3], vec![0xaa]]),
+        &resolved_inputs,
+    )
+    .unwrap();
+    assert_ne!(no_empty_witness, smh);
+}
+
+#[test]
+fn test_otx_signing_message_hash_cell_dep_data_golden() {
+    let raw_tx = RawTransaction::new_builder()
+        .cell_deps(vec![CellDep::default()].pack())
+        .header_deps(vec![[0x11u8; 32].pack(), [0x22u8; 32].pack()].pack())
+        .build();
+    let message = Message::new_builder().build();
+    let signing_range = OtxSigningRange::default()
+        .with_cell_deps(0, 1)
+        .with_header_deps(0, 2)
+        .with_cell_dep_data(true);
+    let cell_deps_data = BytesVec::new_builder()
+        .push(Bytes::from(vec![1, 2, 3]).pack())
+        .build();
+    let smh = host::otx_signing_message_hash_with_cell_dep_data(
+        &message,
+        &raw_tx,
+        &ResolvedInputs::default(),
+        &cell_deps_data,
+        &signing_range,
+    )
+    .unwrap();
+    assert_eq!(
+        smh,
+        [
+            0xc6, 0xd6, 0x91, 0x53, 0x85, 0x37, 0x1a, 0x4d, 0x28, 0x8a, 0x95, 0x6d, 0x59, 0x74,
+            0xa8, 0x33, 0xb2, 0x11, 0x81, 0xb2, 0xd0, 0x89, 0xfd, 0x40, 0xd2, 0x91, 0x5f, 0x31,
+            0xf9, 0x91, 0x28, 0xf5
+        ]
+    );
+
+    // the data of the cell deps is required
+    assert!(matches!(
+        host::otx_signing_message_hash(
+            &message,
+            &raw_tx,
+            &ResolvedInputs::default(),
+            &signing_range
+        ),
+        Err(CobuildError::WrongCount)
+    ));
+}
+
+#[test]
+fn test_otx_signing_message_hash_output_mask_golden() {
+    let raw_tx_with_capacity = |capacity: u64| {
+        RawTransaction::new_builder()
+            .outputs(vec![CellOutput::new_builder().capacity(capacity.pack()).build()].pack())
+            .outputs_data(vec![Bytes::from(vec![0x2a]).pack()].pack())
+            .build()
+    };
+    let message = Message::new_builder().build();
+    let smh = |raw_tx: &RawTransaction, signing_range: &OtxSigningRange| {
+        host::otx_signing_message_hash(&message, raw_tx, &ResolvedInputs::default(), signing_range)
+            .unwrap()
+    };
+    let unmasked = OtxSigningRange::default().with_outputs(0, 1);
+    let masked = unmasked.with_output_hash_mask(OutputHashMask {
+        exclude_capacity: true,
+    });
+
+    let raw_tx = raw_tx_with_capacity(1000);
+    assert_eq!(
+        smh(&raw_tx, &unmasked),
+        [
+            0x36, 0xec, 0x5c, 0x80, 0xfa, 0x1f, 0x2d, 0xec, 0x1f, 0xe9, 0x8e, 0xf8, 0xeb, 0x6f,
+            0x6e, 0xe4, 0xed, 0x4b, 0x7d, 0x00, 0x77, 0x35, 0xe6, 0x7d, 0x22, 0x77, 0xa1, 0xf9,
+            0xf7, 0x14, 0x63, 0x26
+        ]
+    );
+    assert_eq!(
+        smh(&raw_tx, &masked),
+        [
+            0xb0, 0x93, 0xfb, 0x35, 0x87, 0x49, 0x55, 0x30, 0x4a, 0x74, 0x4e, 0x6a, 0x6e, 0x73,
+            0xaa, 0x62, 0x47, 0xe7, 0x90, 0x7b, 0x74, 0xcc, 0xb0, 0x8f, 0xce, 0x19, 0xf7, 0xc0,
+            0x14, 0x08, 0xb1, 0x30
+        ]
+    );
+
+    // the capacity is only covered without the mask
+    let other_raw_tx = raw_tx_with_capacity(2000);
+    assert_ne!(smh(&raw_tx, &unmasked), smh(&other_raw_tx, &unmasked));
+    assert_eq!(smh(&raw_tx, &masked), smh(&other_raw_tx, &masked));
+
+    // the type is framed: with a type script, or without one and its bytes
+    // moved into the data, the outputs don't hash the same
+    let type_script = Script::new_builder()
+        .args(Bytes::from(vec![1, 2, 3]).pack())
+        .build();
+    let with_type = RawTransaction::new_builder()
+        .outputs(
+            vec![CellOutput::new_builder()
+                .type_(Some(type_script.clone()).pack())
+                .build()]
+            .pack(),
+        )
+        .outputs_data(vec![Bytes::new().pack()].pack())
+        .build();
+    let type_in_data = RawTransaction::new_builder()
+        .outputs(vec![CellOutput::new_builder().build()].pack())
+        .outputs_data(
+            vec![Bytes::from([&type_script.as_slice()[4..], &[0u8; 4][..]].concat()).pack()].pack(),
+        )
+        .build();
+    assert_ne!(smh(&with_type, &masked), smh(&type_in_data, &masked));
+
+    // the on-chain hasher agrees
+    let lazy_raw_tx =
+        lazy_blockchain::RawTransaction::from(new_cursor_from_vec(raw_tx.as_slice().to_vec()));
+    let lazy_resolved_inputs = lazy_basic::ResolvedInputs::from(new_cursor_from_vec(
+        ResolvedInputs::default().as_slice().to_vec(),
+    ));
+    let lazy_message = lazy_basic::Message::from(new_cursor_from_vec(message.as_slice().to_vec()));
+    assert_eq!(
+        otx_signing_message_hash_for(&lazy_raw_tx, lazy_message, masked, &lazy_resolved_inputs)
+            .unwrap(),
+        smh(&raw_tx, &masked)
+    );
+}
+
+#[test]
+fn test_no_witnesses() {
+    let (context, _privkey, _lock_script, tx, _resolved_inputs) = gen_otx_tx(1, 1);
+    assert_eq!(tx.witnesses().len(), 0);
+
+    // cobuild isn't activated, the OTX lock demo rejects it without panic
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+}
+
+#[test]
+fn test_single_non_cobuild_witness() {
+    let (context, _privkey, _lock_script, tx, _resolved_inputs) = gen_otx_tx(1, 1);
+    let tx = tx
+        .as_advanced_builder()
+        .witness(WitnessArgs::new_builder().build().as_bytes().pack())
+        .build();
+
+    // cobuild isn't activated, the OTX lock demo rejects it without panic
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+}
+
+#[test]
+fn test_success_otx_builder() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+    let seal_pair = otx_seal_pair(&privkey, &lock_script, &smh);
+
+    let otx = OtxBuilder::new()
+        .fixed_inputs(1)
+        .fixed_outputs(1)
+        .message(message)
+        .seal(
+            lock_script
+                .calc_script_hash()
+                .as_slice()
+                .try_into()
+                .unwrap(),
+            seal_pair.seal().raw_data(),
+        )
+        .build()
+        .unwrap();
+    let witnesses = vec![builder::otx_start_witness(0, 0, 0, 0), otx];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // at least one fixed count must be non-zero
+    assert!(matches!(
+        OtxBuilder::new().dynamic_inputs(1).build(),
+        Err(CobuildError::WrongCount)
+    ));
+}
+
+#[test]
+fn test_otx_overlapping_ranges() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    // the second OTX wraps the input range around to 0, so the third OTX
+    // would consume input 0 again
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+        ),
+        otx_witness(
+            0,
+            [u32::MAX, 0, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![],
+        ),
+        otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message,
+            vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 10); // return Error::WrongCount
+}
+
+#[test]
+fn test_otx_counts_overflow() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    // fixed + dynamic outputs overflow u32 before being added to the end of
+    // the outputs range
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0b0010,
+            [1, u32::MAX, 0, 0],
+            [0, 1, 0, 0],
+            message.clone(),
+            vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 10); // return Error::WrongCount
+
+    // the host side rejects signing ranges whose end overflows
+    let signing_range = OtxSigningRange::default().with_inputs(u32::MAX, 1);
+    assert!(matches!(
+        host::otx_signing_message_hash(
+            &message,
+            &tx.data().raw(),
+            &resolved_inputs,
+            &signing_range
+        ),
+        Err(CobuildError::WrongCount)
+    ));
+}
+
+#[test]
+fn test_otx_strict_duplicated_seals() {
+    // the lock args flag 1 enables OtxStrictness::Strict
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx_with_flags(1, 1, &[1]);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    let build_tx = |seals: Vec<SealPair>| {
+        let witnesses = vec![
+            otx_start_witness([0, 0, 0, 0]),
+            otx_witness(0, [1, 1, 0, 0], [0, 0, 0, 0], message.clone(), seals),
+        ];
+        tx.as_advanced_builder()
+            .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+            .build()
+    };
+
+    let seal_pair = otx_seal_pair(&privkey, &lock_script, &smh);
+    context
+        .verify_tx(&build_tx(vec![seal_pair.clone()]), MAX_CYCLES)
+        .expect("pass verification");
+
+    // the second seal is never verified, the lenient mode would accept it
+    let extra_seal_pair = SealPair::new_builder()
+        .script_hash(lock_script.calc_script_hash())
+        .seal(Bytes::from(vec![0u8; 65]).pack())
+        .build();
+    let err = context
+        .verify_tx(&build_tx(vec![seal_pair, extra_seal_pair]), MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 14); // return Error::WrongOtx
+}
+
+#[test]
+fn test_otx_strict_trailing_witnesses() {
+    // the lock args flag 1 enables OtxStrictness::Strict
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx_with_flags(1, 1, &[1]);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+    let otx = otx_witness(
+        0,
+        [1, 1, 0, 0],
+        [0, 0, 0, 0],
+        message,
+        vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+    );
+    let build_tx = |trailing: Bytes| {
+        let witnesses = vec![otx_start_witness([0, 0, 0, 0]), otx.clone(), trailing];
+        tx.as_advanced_builder()
+            .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+            .build()
+    };
+
+    // a witness which isn't a WitnessLayout is fine
+    context
+        .verify_tx(&build_tx(Bytes::from(vec![1, 2, 3])), MAX_CYCLES)
+        .expect("pass verification");
+
+    let mut unknown = 0xFF000005u32.to_le_bytes().to_vec();
+    unknown.extend_from_slice(&[1, 2, 3]);
+    for (trailing, err_code) in [
+        // return Error::WrongWitnessLayout
+        (Bytes::from(unknown), 7),
+        // return Error::WrongOtxStart, caught in any mode
+        (otx_start_witness([1, 1, 0, 0]), 8),
+    ] {
+        let err = context
+            .verify_tx(&build_tx(trailing), MAX_CYCLES)
+            .expect_err("fail verification");
+        assert_script_error(err, err_code);
+    }
+}
+
+#[test]
+fn test_validate_otx_totals() {
+    let tx_lens = [4, 3, 1, 0];
+    // two OTXs consuming inputs [1, 4) and outputs [0, 2), the last output
+    // and the first input are left to the normal entry
+    assert_eq!(
+        validate_otx_totals([1, 0, 0, 0], &[[1, 1, 1, 0], [2, 1, 0, 0]], tx_lens).unwrap(),
+        [4, 2, 1, 0]
+    );
+    // no OTX consumes nothing
+    assert_eq!(
+        validate_otx_totals([1, 0, 0, 0], &[], tx_lens).unwrap(),
+        [1, 0, 0, 0]
+    );
+
+    // the OTXs claim more inputs, or more header deps, than the transaction has
+    for group_counts in [[[2, 1, 0, 0], [2, 0, 0, 0]], [[1, 0, 0, 0], [0, 0, 0, 1]]] {
+        assert!(matches!(
+            validate_otx_totals([1, 0, 0, 0], &group_counts, tx_lens),
+            Err(CobuildError::WrongCount)
+        ));
+    }
+    // overflow
+    assert!(matches!(
+        validate_otx_totals([1, 0, 0, 0], &[[u32::MAX, 0, 0, 0]], [usize::MAX; 4]),
+        Err(CobuildError::WrongCount)
+    ));
+}
+
+// The second OTX claims more inputs than the transaction has: it's rejected
+// before its seal is verified, only the one of the first OTX is.
+#[test]
+fn test_mock_otx_overrun() {
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 0);
+    let message = Message::new_builder().build();
+    let seal = otx_seal_pair(&privkey, &lock_script, &[0u8; 32]);
+    let witnesses = [
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0,
+            [1, 0, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![seal.clone()],
+        ),
+        otx_witness(0, [2, 0, 0, 0], [0, 0, 0, 0], message, vec![seal]),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+
+    let mut recorder = SmhRecorder::default();
+    assert!(matches!(
+        cobuild_entry_detailed(&mut recorder),
+        Err(CobuildError::WrongCount)
+    ));
+    assert_eq!(recorder.0.len(), 1);
+}
+
+// Input 0 is locked by the current script, outside of the OTX, and input 1
+// by the same code with different args, inside the OTX.
+#[test]
+fn test_mock_script_match_code_hash() {
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 0);
+    let other_lock = lock_script
+        .clone()
+        .as_builder()
+        .args(Bytes::from(vec![0u8; 20]).pack())
+        .build();
+    let resolved_inputs = resolved_inputs
+        .clone()
+        .as_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .push(resolved_inputs.outputs().get(0).unwrap())
+                .push(
+                    resolved_inputs
+                        .outputs()
+                        .get(1)
+                        .unwrap()
+                        .as_builder()
+                        .lock(other_lock.clone())
+                        .build(),
+                )
+                .build(),
+        )
+        .build();
+    let sighash_all = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAll(
+            SighashAll::new_builder()
+                .seal(Bytes::from(vec![0u8; 65]).pack())
+                .build(),
+        ))
+        .build()
+        .as_bytes();
+    let set_otx_seals = |seals: Vec<SealPair>| {
+        let witnesses = [
+            sighash_all.clone(),
+            otx_start_witness([1, 0, 0, 0]),
+            otx_witness(0, [1, 0, 0, 0], [0, 0, 0, 0], Message::default(), seals),
+        ];
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+            .build();
+        set_mock_transaction(MockTransaction::new(
+            tx.data(),
+            resolved_inputs.clone(),
+            lock_script.clone(),
+        ));
+    };
+    let run = |script_match| {
+        let mut recorder = SmhRecorder::default();
+        cobuild_entry_with_script_match(&mut recorder, script_match).map(|_| recorder.0.len())
+    };
+
+    // the OTX only carries the seal of the other lock: it's ignored when
+    // matching by script hash, and required when matching by code hash
+    set_otx_seals(vec![otx_seal_pair(&privkey, &other_lock, &[0u8; 32])]);
+    assert_eq!(run(ScriptMatch::ScriptHash).unwrap(), 1);
+    assert!(matches!(
+        run(ScriptMatch::CodeHash),
+        Err(CobuildError::NoSealFound)
+    ));
+
+    // with a seal of the current script, the OTX is also verified by it
+    set_otx_seals(vec![otx_seal_pair(&privkey, &lock_script, &[0u8; 32])]);
+    assert_eq!(run(ScriptMatch::ScriptHash).unwrap(), 1);
+    assert_eq!(run(ScriptMatch::CodeHash).unwrap(), 2);
+}
+
+#[test]
+fn test_otx_count_exceeds_limit() {
+    let otx_count = MAX_OTX_COUNT + 1;
+    let (context, _privkey, _lock_script, tx, _resolved_inputs) = gen_otx_tx(1, otx_count);
+    let message = Message::new_builder().build();
+
+    // each OTX claims one output, none of them contains the lock
+    let mut witnesses = vec![otx_start_witness([0, 0, 0, 0])];
+    for _ in 0..otx_count {
+        witnesses.push(otx_witness(
+            0,
+            [0, 1, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![],
+        ));
+    }
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 10); // return Error::WrongCount
+}
+
+// The OTX consumes all inputs, so the lock doesn't need to look for its
+// inputs outside of OTXs. The cycles stay within the budget of a single
+// signature verification plus hashing.
+#[test]
+fn test_success_otx_consumes_all_inputs_cycles() {
+    let inputs_count = 16;
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(inputs_count, 1);
+    let message = Message::new_builder().build();
+    let smh = generate_otx_signing_message_hash(&message, &tx.data().raw(), &resolved_inputs);
+
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0,
+            [inputs_count as u32, 1, 0, 0],
+            [0, 0, 0, 0],
+            message,
+            vec![otx_seal_pair(&privkey, &lock_script, &smh)],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+    assert!(cycles < 10_000_000, "consume cycles: {}", cycles);
+}
+
+// The lock is in an OTX and also in an input outside of it, so both the OTX
+// and the SighashAllOnly signing message hashes read the OTX input cells.
+// Those are only loaded once, the consumed cycles are printed for comparison.
+#[test]
+fn test_success_otx_and_sighash_all_only() {
+    let inputs_count = 16;
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(inputs_count, 1);
+    let message = Message::new_builder().build();
+
+    // input 0 is outside of the OTX, inputs 1.. are in it
+    let (otx, otx_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 1..inputs_count, 0..0);
+    let otx_smh = generate_otx_signing_message_hash(&message, &otx, &otx_inputs);
+
+    let sighash_all_only = |seal: Vec<u8>| {
+        WitnessLayout::new_builder()
+            .set(WitnessLayoutUnion::SighashAllOnly(
+                SighashAllOnly::new_builder()
+                    .seal(Bytes::from(seal).pack())
+                    .build(),
+            ))
+            .build()
+            .as_bytes()
+    };
+    // the other witnesses in the script group must be empty, the OTX
+    // witnesses follow them
+    let mut witnesses = vec![sighash_all_only(vec![0u8; 65])];
+    witnesses.resize(inputs_count, Bytes::new());
+    witnesses.push(otx_start_witness([1, 0, 0, 0]));
+    witnesses.push(otx_witness(
+        0,
+        [inputs_count as u32 - 1, 0, 0, 0],
+        [0, 0, 0, 0],
+        message,
+        vec![otx_seal_pair(&privkey, &lock_script, &otx_smh)],
+    ));
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+    // start_input_cell is 1, so input 0 before the OTX is left to the normal
+    // entry, which rejects the placeholder seal
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+
+    // the seal of SighashAllOnly isn't covered by its signing message hash
+    let smh = host::signing_message_hash(&None, &tx.data(), &resolved_inputs).unwrap();
+    let signature = privkey
+        .sign_recoverable(&SecpMessage::from_slice(&smh).unwrap())
+        .unwrap()
+        .serialize();
+    witnesses[0] = sighash_all_only(signature);
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+// The Schnorr lock demo verifies 64-byte BIP-340 signatures against the
+// x-only public key in its args, through the same `Callback` interface.
+#[test]
+fn test_schnorr_lock_demo() {
+    let mut context = Context::default();
+    let out_point =
+        context.deploy_cell(Loader::default().load_binary("transaction-cobuild-schnorr-lock-demo"));
+
+    let secp = secp256k1::Secp256k1::new();
+    let keypair = loop {
+        let mut seckey = [0u8; 32];
+        thread_rng().fill_bytes(&mut seckey);
+        if let Ok(keypair) = secp256k1::Keypair::from_seckey_slice(&secp, &seckey) {
+            break keypair;
+        }
+    };
+    let pubkey = keypair.x_only_public_key().0.serialize();
+    let lock_script = context
+        .build_script(&out_point, Bytes::from(pubkey.to_vec()))
+        .expect("script");
+
+    let mut tx = TransactionBuilder::default();
+    let mut resolved_outputs = CellOutputVec::new_builder();
+    let mut resolved_outputs_data = BytesVec::new_builder();
+    for _ in 0..2 {
+        let input_cell = CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build();
+        let input_out_point = context.create_cell(input_cell.clone(), Bytes::new());
+        resolved_outputs = resolved_outputs.push(input_cell);
+        resolved_outputs_data = resolved_outputs_data.push(Default::default());
+        tx = tx.input(
+            CellInput::new_builder()
+                .previous_output(input_out_point)
+                .build(),
+        );
+    }
+    let tx = context.complete_tx(
+        tx.output(
+            CellOutput::new_builder()
+                .capacity(500u64.pack())
+                .lock(lock_script)
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build(),
+    );
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(resolved_outputs.build())
+        .outputs_data(resolved_outputs_data.build())
+        .build();
+
+    let with_seal = |seal: Vec<u8>| {
+        let witness = WitnessLayout::new_builder()
+            .set(WitnessLayoutUnion::SighashAllOnly(
+                SighashAllOnly::new_builder()
+                    .seal(Bytes::from(seal).pack())
+                    .build(),
+            ))
+            .build()
+            .as_bytes();
+        tx.as_advanced_builder()
+            .set_witnesses(vec![witness.pack(), Bytes::new().pack()])
+            .build()
+    };
+    // the seal of SighashAllOnly isn't covered by its signing message hash
+    let smh =
+        host::signing_message_hash(&None, &with_seal(vec![]).data(), &resolved_inputs).unwrap();
+    let signature = secp
+        .sign_schnorr_no_aux_rand(&secp256k1::Message::from_digest(smh), &keypair)
+        .serialize();
+
+    let cycles = context
+        .verify_tx(&with_seal(signature.to_vec()), MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+
+    let mut tampered = signature;
+    tampered[0] ^= 1;
+    let err = context
+        .verify_tx(&with_seal(tampered.to_vec()), MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+
+    // a recoverable signature has the wrong length
+    let err = context
+        .verify_tx(&with_seal(vec![0u8; 65]), MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+}
+
+// Records the signing message hashes passed to the verifier, with the
+// `mock-syscalls` feature. Seals aren't verified.
+#[derive(Default)]
+struct SmhRecorder(Vec<[u8; 32]>);
+
+impl Callback for SmhRecorder {
+    fn invoke(
+        &mut self,
+        _seal: &[u8],
+        signing_message_hash: &[u8; 32],
+    ) -> Result<(), CobuildError> {
+        self.0.push(*signing_message_hash);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_mock_sighash_all_only() {
+    let mut witnesses = MessageWitnesses::new(vec![2, 1], vec![]);
+    let (tx, resolved_inputs, _context) = gen_tx(&witnesses);
+    let tx = sign_tx(&mut witnesses, tx, resolved_inputs.clone());
+    let lock_script = resolved_inputs.outputs().get(0).unwrap().lock();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs.clone(),
+        lock_script,
+    ));
+
+    let mut recorder = SmhRecorder::default();
+    let result = cobuild_entry_detailed(&mut recorder).unwrap();
+    assert_eq!(
+        result,
+        CobuildResult {
+            activated: true,
+            execution_count: 1,
+            otx_count: 0,
+            normal_entry_invoked: true,
+        }
+    );
+    let smh = host::signing_message_hash(&None, &tx.data(), &resolved_inputs).unwrap();
+    assert_eq!(recorder.0, vec![smh]);
+}
+
+#[test]
+fn test_mock_shared_transaction() {
+    let witnesses = MessageWitnesses::new(vec![2, 1], vec![]);
+    let (tx, resolved_inputs, _context) = gen_tx(&witnesses);
+    let lock_script = resolved_inputs.outputs().get(0).unwrap().lock();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs.clone(),
+        lock_script.clone(),
+    ));
+    let shared = shared_transaction();
+    assert!(Rc::ptr_eq(&shared, &shared_transaction()));
+    let witness_count = tx.witnesses().len();
+    assert_eq!(shared.witnesses().unwrap().len().unwrap(), witness_count);
+
+    // a new mocked transaction is a new script execution
+    let tx = tx
+        .as_advanced_builder()
+        .witness(Bytes::new().pack())
+        .build();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+    let new_shared = shared_transaction();
+    assert!(!Rc::ptr_eq(&shared, &new_shared));
+    assert_eq!(
+        new_shared.witnesses().unwrap().len().unwrap(),
+        witness_count + 1
+    );
+}
+
+#[test]
+fn test_mock_otx_and_sighash_all_only() {
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(3, 1);
+    let message = Message::new_builder().build();
+    let (otx, otx_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 1..3, 0..0);
+    let otx_smh = generate_otx_signing_message_hash(&message, &otx, &otx_inputs);
+    let sighash_all_only = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAllOnly(
+            SighashAllOnly::new_builder()
+                .seal(Bytes::from(vec![0u8; 65]).pack())
+                .build(),
+        ))
+        .build()
+        .as_bytes();
+    let with_otx = |start: u32, fixed_inputs: u32| {
+        let witnesses = [
+            sighash_all_only.clone(),
+            Bytes::new(),
+            Bytes::new(),
+            otx_start_witness([start, 0, 0, 0]),
+            otx_witness(
+                0,
+                [fixed_inputs, 0, 0, 0],
+                [0, 0, 0, 0],
+                message.clone(),
+                vec![otx_seal_pair(&privkey, &lock_script, &otx_smh)],
+            ),
+        ];
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+            .build();
+        set_mock_transaction(MockTransaction::new(
+            tx.data(),
+            resolved_inputs.clone(),
+            lock_script.clone(),
+        ));
+        tx
+    };
+
+    // input 0 is left to the normal entry (step 8), after the OTX
+    let tx = with_otx(1, 2);
+    let mut recorder = SmhRecorder::default();
+    let result = cobuild_entry_detailed(&mut recorder).unwrap();
+    assert_eq!(
+        result,
+        CobuildResult {
+            activated: true,
+            execution_count: 2,
+            otx_count: 1,
+            normal_entry_invoked: true,
+        }
+    );
+    let smh = host::signing_message_hash(&None, &tx.data(), &resolved_inputs).unwrap();
+    assert_eq!(recorder.0, vec![otx_smh, smh]);
+
+    // the OTX claims more inputs than left after its start
+    with_otx(2, 2);
+    assert!(matches!(
+        cobuild_entry_detailed(SmhRecorder::default()),
+        Err(CobuildError::WrongCount)
+    ));
+}
+
+// Input 0 is signed by SighashAll, inputs 1 and 2 by an OTX.
+#[test]
+fn test_mock_all_signing_message_hashes() {
+    let (_context, _privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(3, 1);
+    let otx_message = Message::new_builder().build();
+    let (otx, otx_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 1..3, 0..0);
+    let otx_smh = generate_otx_signing_message_hash(&otx_message, &otx, &otx_inputs);
+    let message = Message::new_builder()
+        .actions(
+            ActionVec::new_builder()
+                .push(
+                    Action::new_builder()
+                        .script_hash(lock_script.calc_script_hash())
+                        .data(Bytes::from(vec![1, 2, 3]).pack())
+                        .build(),
+                )
+                .build(),
+        )
+        .build();
+    let sighash_all = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAll(
+            SighashAll::new_builder().message(message.clone()).build(),
+        ))
+        .build()
+        .as_bytes();
+    // seals aren't needed
+    let witnesses = [
+        sighash_all,
+        Bytes::new(),
+        Bytes::new(),
+        otx_start_witness([1, 0, 0, 0]),
+        otx_witness(0, [2, 0, 0, 0], [0, 0, 0, 0], otx_message, vec![]),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs.clone(),
+        lock_script.clone(),
+    ));
+
+    let smh = host::signing_message_hash(&Some(message), &tx.data(), &resolved_inputs).unwrap();
+    let script_hash: [u8; 32] = lock_script
+        .calc_script_hash()
+        .as_slice()
+        .try_into()
+        .unwrap();
+    assert_eq!(
+        all_signing_message_hashes().unwrap(),
+        vec![(script_hash, otx_smh), (script_hash, smh)]
+    );
+}
+
+// Benchmark of the input cell cache: the input cells of the OTX are read again
+// by the `SighashAll` signing message hash. Each cell load is a syscall in the
+// VM, so the loads saved are cycles saved.
+#[test]
+fn test_mock_cell_cache_loads() {
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(20, 1);
+    let message = Message::new_builder().build();
+    let (otx, otx_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 1..20, 0..0);
+    let otx_smh = generate_otx_signing_message_hash(&message, &otx, &otx_inputs);
+    let sighash_all = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAll(
+            SighashAll::new_builder()
+                .seal(Bytes::from(vec![0u8; 65]).pack())
+                .build(),
+        ))
+        .build()
+        .as_bytes();
+    let mut witnesses = vec![sighash_all];
+    witnesses.resize(20, Bytes::new());
+    witnesses.push(otx_start_witness([1, 0, 0, 0]));
+    witnesses.push(otx_witness(
+        0,
+        [19, 0, 0, 0],
+        [0, 0, 0, 0],
+        message,
+        vec![otx_seal_pair(&privkey, &lock_script, &otx_smh)],
+    ));
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+
+    let loads = |max_cached_cell_bytes| {
+        set_mock_transaction(MockTransaction::new(
+            tx.data(),
+            resolved_inputs.clone(),
+            lock_script.clone(),
+        ));
+        let limits = CobuildLimits {
+            max_cached_cell_bytes,
+            ..Default::default()
+        };
+        assert!(cobuild_entry_with_limits(SmhRecorder::default(), limits).unwrap());
+        cell_load_count()
+    };
+    let uncached = loads(0);
+    let cached = loads(CobuildLimits::default().max_cached_cell_bytes);
+    // room for 4 cursors only, the others are set up again on every access
+    let capped = loads(4 * MAX_CACHE_SIZE);
+    println!("cell loads: {uncached} uncached, {cached} cached, {capped} capped");
+    assert!(cached < capped);
+    assert!(capped < uncached);
+}
+
+// A type script reads the message of a SighashAll witness whose seal is
+// empty, which the lock flow rejects.
+#[test]
+fn test_mock_fetch_message_only() {
+    let mut witnesses = MessageWitnesses::new(vec![1], vec![]);
+    witnesses.set_with_action(0);
+    witnesses.message_data[0].sign = Some(vec![]);
+    let (tx, resolved_inputs, _context) = gen_tx(&witnesses);
+    let lock_script = resolved_inputs.outputs().get(0).unwrap().lock();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+
+    let message = fetch_message_only().unwrap().expect("message");
+    let message: Vec<u8> = message.cursor.try_into().unwrap();
+    let expected = witnesses.message_data[0].action.as_ref().unwrap();
+    assert_eq!(message, expected.as_slice());
+
+    let err = cobuild_entry_detailed(&mut SmhRecorder::default()).unwrap_err();
+    assert!(matches!(err, CobuildError::NoSealFound));
+}
+
+#[test]
+fn test_mock_fetch_seal_at() {
+    let (_context, _privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(3, 0);
+    let witnesses = [
+        WitnessLayout::new_builder()
+            .set(WitnessLayoutUnion::SighashAll(
+                SighashAll::new_builder()
+                    .seal(Bytes::from(vec![1, 2, 3]).pack())
+                    .build(),
+            ))
+            .build()
+            .as_bytes(),
+        WitnessLayout::new_builder()
+            .set(WitnessLayoutUnion::SighashAllOnly(
+                SighashAllOnly::new_builder()
+                    .seal(Bytes::from(vec![4, 5]).pack())
+                    .build(),
+            ))
+            .build()
+            .as_bytes(),
+        otx_start_witness([0, 0, 0, 0]),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+
+    assert_eq!(fetch_seal_at(0, Source::Input).unwrap(), vec![1, 2, 3]);
+    assert_eq!(fetch_seal_at(1, Source::GroupInput).unwrap(), vec![4, 5]);
+    assert!(matches!(
+        fetch_seal_at(2, Source::Input),
+        Err(CobuildError::WrongWitnessLayout)
+    ));
+    assert!(matches!(
+        fetch_seal_at(3, Source::Input),
+        Err(CobuildError::Sys(SysError::IndexOutOfBound))
+    ));
+}
+
+// Records the context of every OTX group, and counts the calls of `invoke`,
+// which OTX groups shouldn't reach.
+#[derive(Default)]
+struct OtxContextRecorder {
+    contexts: Vec<OtxContext>,
+    invoked: usize,
+}
+
+impl Callback for OtxContextRecorder {
+    fn invoke(&mut self, _seal: &[u8], _smh: &[u8; 32]) -> Result<(), CobuildError> {
+        self.invoked += 1;
+        Ok(())
+    }
+
+    fn invoke_otx(
+        &mut self,
+        _seal: &[u8],
+        _smh: &[u8; 32],
+        ctx: &OtxContext,
+    ) -> Result<(), CobuildError> {
+        self.contexts.push(*ctx);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_mock_otx_context() {
+    // the first OTX has input 0 and output 0, the second one input 1, and
+    // input 2 as dynamic input
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(3, 1);
+    let seal = otx_seal_pair(&privkey, &lock_script, &[0u8; 32]);
+    let mut witnesses = vec![Bytes::new(); 3];
+    witnesses.push(otx_start_witness([0, 0, 0, 0]));
+    witnesses.push(otx_witness(
+        0,
+        [1, 1, 0, 0],
+        [0, 0, 0, 0],
+        Message::default(),
+        vec![seal.clone()],
+    ));
+    witnesses.push(otx_witness(
+        1,
+        [1, 0, 0, 0],
+        [1, 0, 0, 0],
+        Message::default(),
+        vec![seal],
+    ));
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+
+    let mut recorder = OtxContextRecorder::default();
+    cobuild_entry_detailed(&mut recorder).unwrap();
+    assert_eq!(recorder.invoked, 0);
+    // (witness_index, flag, input_start, output_start, fixed inputs, fixed
+    // outputs, dynamic inputs, dynamic)
+    let contexts = recorder
+        .contexts
+        .iter()
+        .map(|ctx| {
+            (
+                ctx.witness_index,
+                ctx.flag,
+                ctx.input_start,
+                ctx.output_start,
+                ctx.fixed_input_cells,
+                ctx.fixed_output_cells,
+                ctx.dynamic_input_cells,
+                ctx.dynamic,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        contexts,
+        vec![
+            (4, 0, 0, 0, 1, 1, 0, false),
+            (5, 1, 1, 1, 1, 0, 1, false),
+            (5, 1, 1, 1, 1, 0, 1, true),
+        ]
+    );
+}
+
+// Records the data of the first action of every message, and rejects the
+// messages whose first action data is `reject`.
+#[derive(Default)]
+struct ActionDataVerifier(Vec<Option<Vec<u8>>>);
+
+impl MessageCallback for ActionDataVerifier {
+    fn invoke(
+        &mut self,
+        _seal: &[u8],
+        _smh: &[u8; 32],
+        message: Option<&lazy_basic::Message>,
+    ) -> Result<(), CobuildError> {
+        let data = match message {
+            Some(message) => Some(Vec::<u8>::try_from(message.actions()?.get(0)?.data()?)?),
+            None => None,
+        };
+        if data.as_deref() == Some(b"reject") {
+            return Err(CobuildError::AuthError);
+        }
+        self.0.push(data);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_mock_cobuild_entry_with_message() {
+    // input 0 is signed with SighashAll, input 1 is in an OTX
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 0);
+    let message = |data: &[u8]| {
+        Message::new_builder()
+            .actions(
+                ActionVec::new_builder()
+                    .push(
+                        Action::new_builder()
+                            .script_hash(lock_script.calc_script_hash())
+                            .data(Bytes::from(data.to_vec()).pack())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build()
+    };
+    let set_messages = |sighash_all: &[u8], otx: &[u8]| {
+        let witnesses = [
+            WitnessLayout::new_builder()
+                .set(WitnessLayoutUnion::SighashAll(
+                    SighashAll::new_builder()
+                        .message(message(sighash_all))
+                        .seal(Bytes::from(vec![0u8; 65]).pack())
+                        .build(),
+                ))
+                .build()
+                .as_bytes(),
+            // the other witnesses of the group must be empty
+            Bytes::new(),
+            otx_start_witness([1, 0, 0, 0]),
+            otx_witness(
+                0,
+                [1, 0, 0, 0],
+                [0, 0, 0, 0],
+                message(otx),
+                vec![otx_seal_pair(&privkey, &lock_script, &[0u8; 32])],
+            ),
+        ];
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+            .build();
+        set_mock_transaction(MockTransaction::new(
+            tx.data(),
+            resolved_inputs.clone(),
+            lock_script.clone(),
+        ));
+    };
+
+    // the OTX group is verified first, with its own message
+    set_messages(b"transfer", b"swap");
+    let mut verifier = ActionDataVerifier::default();
+    assert!(cobuild_entry_with_message(&mut verifier).unwrap());
+    assert_eq!(
+        verifier.0,
+        vec![Some(b"swap".to_vec()), Some(b"transfer".to_vec())]
+    );
+
+    // the verifier rejects either message
+    for (sighash_all, otx) in [(&b"reject"[..], &b"swap"[..]), (b"transfer", b"reject")] {
+        set_messages(sighash_all, otx);
+        assert!(matches!(
+            cobuild_entry_with_message(ActionDataVerifier::default()),
+            Err(CobuildError::AuthError)
+        ));
+    }
+}
+
+#[test]
+fn test_mock_cell_dep_actions_in_entries() {
+    // input 0 is signed with SighashAll, input 1 is in an OTX
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 0);
+    let governance_lock = Script::new_builder()
+        .args(Bytes::from(vec![1]).pack())
+        .build();
+    let resolved_cell_deps = ResolvedInputs::new_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .push(
+                    CellOutput::new_builder()
+                        .lock(governance_lock.clone())
+                        .build(),
+                )
+                .build(),
+        )
+        .outputs_data(BytesVec::new_builder().push(Default::default()).build())
+        .build();
+    let message = |script_type: u8| {
+        Message::new_builder()
+            .actions(
+                ActionVec::new_builder()
+                    .push(
+                        Action::new_builder()
+                            .script_hash(governance_lock.calc_script_hash())
+                            .script_type(script_type.into())
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build()
+    };
+    let set_messages = |sighash_all: u8, otx: u8| {
+        let witnesses = [
+            WitnessLayout::new_builder()
+                .set(WitnessLayoutUnion::SighashAll(
+                    SighashAll::new_builder()
+                        .message(message(sighash_all))
+                        .seal(Bytes::from(vec![0u8; 65]).pack())
+                        .build(),
+                ))
+                .build()
+                .as_bytes(),
+            Bytes::new(),
+            otx_start_witness([1, 0, 0, 0]),
+            otx_witness(
+                0,
+                [1, 0, 0, 0],
+                [0, 0, 0, 0],
+                message(otx),
+                vec![otx_seal_pair(&privkey, &lock_script, &[0u8; 32])],
+            ),
+        ];
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+            .build();
+        set_mock_transaction(
+            MockTransaction::new(tx.data(), resolved_inputs.clone(), lock_script.clone())
+                .with_resolved_cell_deps(resolved_cell_deps.clone()),
+        );
+    };
+
+    // both messages name the lock of the cell dep
+    set_messages(5, 5);
+    assert!(cobuild_entry_with_message(ActionDataVerifier::default()).unwrap());
+    verify_otx_group(SmhRecorder::default(), 0).unwrap();
+
+    // the governance lock locks no input
+    for (sighash_all, otx) in [(0, 5), (5, 0)] {
+        set_messages(sighash_all, otx);
+        assert!(matches!(
+            cobuild_entry_with_message(ActionDataVerifier::default()),
+            Err(CobuildError::ScriptHashAbsent)
+        ));
+    }
+    set_messages(5, 0);
+    assert!(matches!(
+        verify_otx_group(SmhRecorder::default(), 0),
+        Err(CobuildError::ScriptHashAbsent)
+    ));
+}
+
+#[test]
+fn test_mock_verify_otx_group() {
+    // three OTXs of one input and one output each
+    let (_context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(3, 3);
+    let message = Message::new_builder().build();
+    let smhs = (0..3)
+        .map(|i| {
+            let (otx, otx_inputs) =
+                otx_range(&tx.data().raw(), &resolved_inputs, i..i + 1, i..i + 1);
+            generate_otx_signing_message_hash(&message, &otx, &otx_inputs)
+        })
+        .collect::<Vec<_>>();
+    let mut witnesses = vec![Bytes::new(); 3];
+    witnesses.push(otx_start_witness([0, 0, 0, 0]));
+    for smh in &smhs {
+        witnesses.push(otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![otx_seal_pair(&privkey, &lock_script, smh)],
+        ));
+    }
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.iter().map(|w| w.pack()).collect())
+        .build();
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+
+    // only the second OTX is verified, at its cumulative offsets
+    let mut recorder = SmhRecorder::default();
+    verify_otx_group(&mut recorder, 1).unwrap();
+    assert_eq!(recorder.0, vec![smhs[1]]);
+    let err = verify_otx_group(SmhRecorder::default(), 3).unwrap_err();
+    assert!(matches!(err, CobuildError::OtxGroupNotFound));
+    assert_eq!(err.code(), 27);
+}
+
+#[test]
+fn test_cell_dep_actions() {
+    let governance_hash = [1u8; 32];
+    let mut cache = BTreeMap::new();
+    cache.insert(
+        governance_hash,
+        ScriptLocation {
+            cell_dep_lock: vec![1],
+            ..Default::default()
+        },
+    );
+    assert!(is_script_exist(
+        &cache,
+        governance_hash,
+        ScriptType::CellDepLock
+    ));
+    assert!(!is_script_exist(
+        &cache,
+        governance_hash,
+        ScriptType::CellDepType
+    ));
+    assert!(!is_script_exist(
+        &cache,
+        governance_hash,
+        ScriptType::InputLock
+    ));
+    assert!(is_script_included(
+        &cache,
+        governance_hash,
+        ScriptType::CellDepLock,
+        1,
+        2
+    ));
+    assert!(!is_script_included(
+        &cache,
+        governance_hash,
+        ScriptType::CellDepLock,
+        0,
+        1
+    ));
+
+    let lazy_message = |script_type: u8| {
+        let action = Action::new_builder()
+            .script_hash(governance_hash.pack())
+            .script_type(script_type.into())
+            .build();
+        let message = Message::new_builder()
+            .actions(ActionVec::new_builder().push(action).build())
+            .build();
+        lazy_basic::Message::from(new_cursor_from_vec(message.as_slice().to_vec()))
+    };
+    assert!(validate_actions(&cache, &lazy_message(5)).is_ok());
+    assert!(matches!(
+        validate_actions(&cache, &lazy_message(6)),
+        Err((0, CobuildError::ScriptHashAbsent))
+    ));
+    assert!(matches!(
+        validate_actions(&cache, &lazy_message(7)),
+        Err((0, CobuildError::WrongScriptType))
+    ));
+}
+
+#[test]
+fn test_mock_cache_script_hashes_cell_deps() {
+    let (_context, _privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(1, 1);
+    let governance_lock = Script::new_builder()
+        .args(Bytes::from(vec![1]).pack())
+        .build();
+    let governance_type = Script::new_builder()
+        .args(Bytes::from(vec![2]).pack())
+        .build();
+    let resolved_cell_deps = ResolvedInputs::new_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .push(CellOutput::new_builder().lock(lock_script.clone()).build())
+                .push(
+                    CellOutput::new_builder()
+                        .lock(governance_lock.clone())
+                        .type_(Some(governance_type.clone()).pack())
+                        .build(),
+                )
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); 2])
+                .build(),
+        )
+        .build();
+    set_mock_transaction(
+        MockTransaction::new(tx.data(), resolved_inputs, lock_script.clone())
+            .with_resolved_cell_deps(resolved_cell_deps),
+    );
+
+    let script_hash =
+        |script: &Script| -> [u8; 32] { script.calc_script_hash().as_slice().try_into().unwrap() };
+    let cache = cache_script_hashes_with_cell_deps();
+    let location = |script: &Script| {
+        script_location(&cache, &script_hash(script))
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(
+        location(&lock_script),
+        ScriptLocation {
+            input_lock: vec![0],
+            output_lock: vec![0],
+            cell_dep_lock: vec![0],
+            ..Default::default()
+        }
+    );
+    assert_eq!(location(&governance_lock).cell_dep_lock, vec![1]);
+    assert_eq!(location(&governance_type).cell_dep_type, vec![1]);
+
+    // cell deps aren't scanned by default
+    let cache = cache_script_hashes();
+    assert_eq!(
+        script_location(&cache, &script_hash(&lock_script)),
+        Some(&ScriptLocation {
+            input_lock: vec![0],
+            output_lock: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        script_location(&cache, &script_hash(&governance_lock)),
+        None
+    );
+    assert_eq!(
+        script_location(&cache, &script_hash(&governance_type)),
+        None
+    );
+}
+
+#[test]
+fn test_mock_script_group_type() {
+    let script = Script::new_builder()
+        .args(Bytes::from(vec![9]).pack())
+        .build();
+    let other = Script::new_builder()
+        .args(Bytes::from(vec![8]).pack())
+        .build();
+    let cell = |lock: &Script, type_: Option<&Script>| {
+        CellOutput::new_builder()
+            .lock(lock.clone())
+            .type_(type_.cloned().pack())
+            .build()
+    };
+    // `script` is the lock of inputs 0 and 2, and the type of inputs 1 and 2
+    // and of output 0
+    let inputs = vec![
+        cell(&script, None),
+        cell(&other, Some(&script)),
+        cell(&script, Some(&script)),
+    ];
+    let outputs = vec![cell(&other, Some(&script)), cell(&script, None)];
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(CellOutputVec::new_builder().set(inputs).build())
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); 3])
+                .build(),
+        )
+        .build();
+    let raw_tx = RawTransaction::new_builder()
+        .inputs(
+            CellInputVec::new_builder()
+                .set(vec![CellInput::default(); 3])
+                .build(),
+        )
+        .outputs(CellOutputVec::new_builder().set(outputs).build())
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); 2])
+                .build(),
+        )
+        .build();
+    let tx = Transaction::new_builder().raw(raw_tx).build();
+    let script_hash =
+        |script: &Script| -> [u8; 32] { script.calc_script_hash().as_slice().try_into().unwrap() };
+
+    // the lock group is made of inputs 0 and 2, without outputs
+    set_mock_transaction(MockTransaction::new(
+        tx.clone(),
+        resolved_inputs.clone(),
+        script.clone(),
+    ));
+    let cache = cache_group_script_hashes();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(
+        script_location(&cache, &script_hash(&script)),
+        Some(&ScriptLocation {
+            input_lock: vec![0, 1],
+            input_type: vec![1],
+            ..Default::default()
+        })
+    );
+
+    // the type group is made of inputs 1 and 2, and output 0
+    set_mock_transaction(
+        MockTransaction::new(tx, resolved_inputs, script.clone())
+            .with_script_group_type(ScriptGroupType::Type),
+    );
+    let cache = cache_group_script_hashes();
+    assert_eq!(
+        script_location(&cache, &script_hash(&script)),
+        Some(&ScriptLocation {
+            input_lock: vec![1],
+            input_type: vec![0, 1],
+            output_type: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        script_location(&cache, &script_hash(&other)),
+        Some(&ScriptLocation {
+            input_lock: vec![0],
+            output_lock: vec![0],
+            ..Default::default()
+        })
+    );
+}
+
+// The global cache uses transaction indices, the group cache uses indices
+// relative to the group of the current script.
+#[test]
+fn test_mock_group_vs_global_cache() {
+    let script = |arg: u8| {
+        Script::new_builder()
+            .args(Bytes::from(vec![arg]).pack())
+            .build()
+    };
+    let (lock, type_a, type_b) = (script(0), script(1), script(2));
+    let cell = |type_: &Script| {
+        CellOutput::new_builder()
+            .lock(lock.clone())
+            .type_(Some(type_.clone()).pack())
+            .build()
+    };
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .set(vec![cell(&type_a), cell(&type_b), cell(&type_a)])
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); 3])
+                .build(),
+        )
+        .build();
+    let raw_tx = RawTransaction::new_builder()
+        .inputs(
+            CellInputVec::new_builder()
+                .set(vec![CellInput::default(); 3])
+                .build(),
+        )
+        .outputs(
+            CellOutputVec::new_builder()
+                .set(vec![cell(&type_b), cell(&type_a)])
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); 2])
+                .build(),
+        )
+        .build();
+    let tx = Transaction::new_builder().raw(raw_tx).build();
+    let location = |cache: &BTreeMap<[u8; 32], ScriptLocation>, script: &Script| {
+        script_location(
+            cache,
+            &script.calc_script_hash().as_slice().try_into().unwrap(),
+        )
+        .cloned()
+    };
+    let set_type_group = |script: &Script| {
+        set_mock_transaction(
+            MockTransaction::new(tx.clone(), resolved_inputs.clone(), script.clone())
+                .with_script_group_type(ScriptGroupType::Type),
+        )
+    };
+
+    set_type_group(&type_a);
+    let global = cache_script_hashes();
+    let group_a = cache_group_script_hashes();
+    set_type_group(&type_b);
+    // the global cache doesn't depend on the current script
+    assert_eq!(cache_script_hashes(), global);
+    let group_b = cache_group_script_hashes();
+
+    assert_eq!(
+        location(&global, &type_a),
+        Some(ScriptLocation {
+            input_type: vec![0, 2],
+            output_type: vec![1],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        location(&global, &type_b),
+        Some(ScriptLocation {
+            input_type: vec![1],
+            output_type: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        location(&global, &lock),
+        Some(ScriptLocation {
+            input_lock: vec![0, 1, 2],
+            output_lock: vec![0, 1],
+            ..Default::default()
+        })
+    );
+
+    // inputs 0 and 2 and output 1, renumbered from 0
+    assert_eq!(
+        location(&group_a, &type_a),
+        Some(ScriptLocation {
+            input_type: vec![0, 1],
+            output_type: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        location(&group_a, &lock),
+        Some(ScriptLocation {
+            input_lock: vec![0, 1],
+            output_lock: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(location(&group_a, &type_b), None);
+
+    // input 1 and output 0
+    assert_eq!(
+        location(&group_b, &type_b),
+        Some(ScriptLocation {
+            input_type: vec![0],
+            output_type: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        location(&group_b, &lock),
+        Some(ScriptLocation {
+            input_lock: vec![0],
+            output_lock: vec![0],
+            ..Default::default()
+        })
+    );
+    assert_eq!(location(&group_b, &type_a), None);
+}
+
+#[test]
+fn test_mock_cache_script_hashes_capped() {
+    // 300 inputs alternating between two locks, and 2 outputs
+    let lock = |arg: u8| {
+        Script::new_builder()
+            .args(Bytes::from(vec![arg]).pack())
+            .build()
+    };
+    let cell = |arg: u8| CellOutput::new_builder().lock(lock(arg)).build();
+    let inputs_count = 300;
+    let resolved_inputs = ResolvedInputs::new_builder()
+        .outputs(
+            CellOutputVec::new_builder()
+                .set((0..inputs_count).map(|i| cell(i as u8 % 2)).collect())
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); inputs_count])
+                .build(),
+        )
+        .build();
+    let raw_tx = RawTransaction::new_builder()
+        .inputs(
+            CellInputVec::new_builder()
+                .set(vec![CellInput::default(); inputs_count])
+                .build(),
+        )
+        .outputs(
+            CellOutputVec::new_builder()
+                .set(vec![cell(2), cell(2)])
+                .build(),
+        )
+        .outputs_data(
+            BytesVec::new_builder()
+                .set(vec![Default::default(); 2])
+                .build(),
+        )
+        .build();
+    let tx = Transaction::new_builder().raw(raw_tx).build();
+    set_mock_transaction(MockTransaction::new(tx, resolved_inputs, lock(0)));
+
+    let count_cells = |cache: &BTreeMap<[u8; 32], ScriptLocation>| {
+        cache.values().fold((0, 0), |(inputs, outputs), location| {
+            (
+                inputs + location.input_lock.len(),
+                outputs + location.output_lock.len(),
+            )
+        })
+    };
+    let (cache, truncated) = cache_script_hashes_capped(100);
+    assert!(truncated);
+    assert_eq!(count_cells(&cache), (100, 0));
+    let (cache, truncated) = cache_script_hashes_capped(301);
+    assert!(truncated);
+    assert_eq!(count_cells(&cache), (300, 1));
+    // exactly all cells
+    let (cache, truncated) = cache_script_hashes_capped(302);
+    assert!(!truncated);
+    assert_eq!(cache, cache_script_hashes());
+    assert_eq!(count_cells(&cache), (300, 2));
+}
+
+#[test]
+fn test_malformed_otx_layouts() {
+    let (context, _privkey, _lock_script, tx, _resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let otx = otx_witness(0, [1, 1, 0, 0], [0, 0, 0, 0], message, vec![]);
+    let start = otx_start_witness([0, 0, 0, 0]);
+
+    for (witnesses, err_code) in [
+        // return Error::DuplicateOtxStart
+        (vec![start.clone(), start.clone(), otx.clone()], 18),
+        // return Error::OtxBeforeStart
+        (vec![otx.clone(), start.clone(), otx.clone()], 19),
+        // return Error::OtxNotContiguous
+        (
+            vec![start.clone(), otx.clone(), Bytes::new(), otx.clone()],
+            20,
+        ),
+        // return Error::WrongWitnessLayout, a SighashAllOnly inside the run
+        (
+            vec![
+                start.clone(),
+                otx.clone(),
+                WitnessLayout::new_builder()
+                    .set(WitnessLayoutUnion::SighashAllOnly(
+                        SighashAllOnly::new_builder().build(),
+                    ))
+                    .build()
+                    .as_bytes(),
+                otx.clone(),
+            ],
+            7,
+        ),
+    ] {
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+            .build();
+        let err = context
+            .verify_tx(&tx, MAX_CYCLES)
+            .expect_err("fail verification");
+        assert_script_error(err, err_code);
+    }
+}
+
+#[test]
+fn test_otx_missing_seals() {
+    let (context, _privkey, _lock_script, tx, _resolved_inputs) = gen_otx_tx(1, 1);
+    let message = Message::new_builder().build();
+    let other_seal = SealPair::new_builder()
+        .script_hash([0u8; 32].pack())
+        .seal(Bytes::from(vec![0u8; 65]).pack())
+        .build();
+
+    for (seals, err_code) in [
+        // return Error::NoSeals
+        (vec![], 24),
+        // return Error::NoSealFound, the seal is for another script
+        (vec![other_seal], 15),
+    ] {
+        let witnesses = vec![
+            otx_start_witness([0, 0, 0, 0]),
+            otx_witness(0, [1, 1, 0, 0], [0, 0, 0, 0], message.clone(), seals),
+        ];
+        let tx = tx
+            .as_advanced_builder()
+            .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+            .build();
+        let err = context
+            .verify_tx(&tx, MAX_CYCLES)
+            .expect_err("fail verification");
+        assert_script_error(err, err_code);
+    }
+}
+
+#[test]
+fn test_script_location() {
+    let hash = [1u8; 32];
+    let empty_hash = [2u8; 32];
+    let mut cache = BTreeMap::new();
+    cache.insert(
+        hash,
+        ScriptLocation {
+            input_lock: vec![],
+            input_type: vec![0],
+            output_type: vec![0],
+            output_lock: vec![],
+            cell_dep_lock: vec![],
+            cell_dep_type: vec![],
+        },
+    );
+    cache.insert(empty_hash, ScriptLocation::default());
+
+    let location = script_location(&cache, &hash).unwrap();
+    assert_eq!(location.input_type, [0]);
+    assert_eq!(location.output_type, [0]);
+    assert!(location.input_lock.is_empty());
+
+    // present with empty vectors is not the same as absent
+    assert!(script_location(&cache, &empty_hash).is_some());
+    assert!(!is_script_exist(&cache, empty_hash, ScriptType::InputType));
+    assert!(script_location(&cache, &[3u8; 32]).is_none());
+}
+
+#[test]
+fn test_is_script_included() {
+    let hash = [1u8; 32];
+    let empty_hash = [2u8; 32];
+    let absent_hash = [3u8; 32];
+    let mut cache = BTreeMap::new();
+    cache.insert(
+        hash,
+        ScriptLocation {
+            input_lock: vec![1, 3],
+            input_type: vec![0],
+            output_type: vec![],
+            output_lock: vec![],
+            cell_dep_lock: vec![],
+            cell_dep_type: vec![],
+        },
+    );
+    cache.insert(
+        empty_hash,
+        ScriptLocation {
+            input_lock: vec![],
+            input_type: vec![],
+            output_type: vec![],
+            output_lock: vec![],
+            cell_dep_lock: vec![],
+            cell_dep_type: vec![],
+        },
+    );
+
+    // in range
+    assert!(is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputLock,
+        0,
+        2
+    ));
+    assert!(is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputLock,
+        3,
+        4
+    ));
+    assert!(is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputType,
+        0,
+        1
+    ));
+    // out of range, end index is exclusive
+    assert!(!is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputLock,
+        0,
+        1
+    ));
+    assert!(!is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputLock,
+        2,
+        3
+    ));
+    assert!(!is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputLock,
+        4,
+        10
+    ));
+    assert!(!is_script_included(
+        &cache,
+        hash,
+        ScriptType::InputLock,
+        1,
+        1
+    ));
+    // empty vectors
+    assert!(!is_script_included(
+        &cache,
+        hash,
+        ScriptType::OutputType,
+        0,
+        10
+    ));
+    assert!(!is_script_included(
+        &cache,
+        empty_hash,
+        ScriptType::InputLock,
+        0,
+        10
+    ));
+    // absent script hash
+    assert!(!is_script_included(
+        &cache,
+        absent_hash,
+        ScriptType::InputLock,
+        0,
+        10
+    ));
+
+    // counts
+    assert_eq!(count_script_cells(&cache, hash, ScriptType::InputLock), 2);
+    assert_eq!(count_script_cells(&cache, hash, ScriptType::InputType), 1);
+    assert_eq!(count_script_cells(&cache, hash, ScriptType::OutputType), 0);
+    assert_eq!(
+        count_script_cells(&cache, absent_hash, ScriptType::InputLock),
+        0
+    );
+}
+
+#[test]
+fn test_input_output_type() {
+    let both_hash = [1u8; 32];
+    let input_only_hash = [2u8; 32];
+    let mut cache = BTreeMap::new();
+    cache.insert(
+        both_hash,
+        ScriptLocation {
+            input_lock: vec![],
+            input_type: vec![0, 2],
+            output_type: vec![1],
+            output_lock: vec![],
+            cell_dep_lock: vec![],
+            cell_dep_type: vec![],
+        },
+    );
+    cache.insert(
+        input_only_hash,
+        ScriptLocation {
+            input_lock: vec![],
+            input_type: vec![1],
+            output_type: vec![],
+            output_lock: vec![],
+            cell_dep_lock: vec![],
+            cell_dep_type: vec![],
+        },
+    );
+
+    assert!(is_script_exist(
+        &cache,
+        both_hash,
+        ScriptType::InputOutputType
+    ));
+    assert!(!is_script_exist(
+        &cache,
+        input_only_hash,
+        ScriptType::InputOutputType
+    ));
+    assert!(is_script_exist(
+        &cache,
+        input_only_hash,
+        ScriptType::InputType
+    ));
+
+    assert!(is_script_included(
+        &cache,
+        both_hash,
+        ScriptType::InputOutputType,
+        2,
+        3
+    ));
+    assert!(!is_script_included(
+        &cache,
+        both_hash,
+        ScriptType::InputOutputType,
+        1,
+        2
+    ));
+    assert!(!is_script_included(
+        &cache,
+        input_only_hash,
+        ScriptType::InputOutputType,
+        0,
+        10
+    ));
+
+    assert_eq!(
+        count_script_cells(&cache, both_hash, ScriptType::InputOutputType),
+        3
+    );
+}
+
+#[test]
+fn test_otx_dynamic_configs_flag() {
+    for flag in 0u8..16 {
+        let configs = OtxDynamicConfigs::try_from(flag).unwrap();
+        assert_eq!(configs.to_flag(), flag);
+        assert_eq!(
+            OtxDynamicConfigs::try_from(configs.to_flag()).unwrap(),
+            configs
+        );
+        assert_eq!(configs.any_dynamic(), flag != 0);
+    }
+    // reserved bits
+    for flag in 16u8..=255 {
+        assert!(OtxDynamicConfigs::try_from(flag).is_err());
+    }
+}
+
+#[test]
+fn test_action_data_array() {
+    let action = Action::new_builder()
+        .data(Bytes::from(vec![7u8; 16]).pack())
+        .build();
+    let action = lazy_basic::Action::from(new_cursor_from_vec(action.as_slice().to_vec()));
+
+    assert_eq!(action.data_array::<16>().unwrap(), [7u8; 16]);
+    // truncated or oversized data
+    assert!(matches!(
+        action.data_array::<32>(),
+        Err(CobuildError::DataLength)
+    ));
+    assert!(matches!(
+        action.data_array::<8>(),
+        Err(CobuildError::DataLength)
+    ));
+}
+
+#[test]
+fn test_seal_schemes() {
+    let mut secp256k1 = vec![SEAL_SCHEME_SECP256K1_RECOVERABLE];
+    secp256k1.extend_from_slice(&[7u8; 65]);
+    let seal = Seal::try_from(&secp256k1[..]).unwrap();
+    assert_eq!(seal, Seal::Secp256k1Recoverable([7u8; 65]));
+    assert_eq!(seal.to_bytes(), secp256k1);
+
+    let mut schnorr = vec![SEAL_SCHEME_SCHNORR];
+    schnorr.extend_from_slice(&[8u8; 64]);
+    assert_eq!(
+        Seal::try_from(&schnorr[..]).unwrap(),
+        Seal::Schnorr([8u8; 64])
+    );
+
+    // a scheme byte with the wrong length, or an unknown scheme, is raw
+    assert_eq!(
+        Seal::try_from(&secp256k1[..64]).unwrap(),
+        Seal::Raw(secp256k1[..64].to_vec())
+    );
+    assert_eq!(
+        Seal::try_from(&[9u8; 65][..]).unwrap(),
+        Seal::Raw(vec![9u8; 65])
+    );
+    assert!(matches!(
+        Seal::try_from(&[][..]),
+        Err(CobuildError::DataLength)
+    ));
+}
+
+#[test]
+fn test_message_actions_indexed() {
+    let actions = (0u8..3)
+        .map(|i| Action::new_builder().script_type(i.into()).build())
+        .collect::<Vec<_>>();
+    let bytes = Message::new_builder()
+        .actions(ActionVec::new_builder().set(actions).build())
+        .build()
+        .as_slice()
+        .to_vec();
+    let message = lazy_basic::Message::from(new_cursor_from_vec(bytes.clone()));
+
+    let indexed = message
+        .actions_indexed()
+        .unwrap()
+        .into_iter()
+        .map(|(index, action)| (index, action.script_type().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(indexed, vec![(0, 0), (1, 1), (2, 2)]);
+    assert_eq!(message.action_at(2).unwrap().script_type().unwrap(), 2);
+    let err = message.action_at(3).err().unwrap();
+    assert!(matches!(err, CobuildError::ActionNotFound));
+    assert_eq!(err.code(), 28);
+
+    // the offset of the second action points past the end of the message
+    let mut malformed = bytes;
+    malformed[16..20].copy_from_slice(&0xFFFFu32.to_le_bytes());
+    let message = lazy_basic::Message::from(new_cursor_from_vec(malformed));
+    assert!(message.actions_indexed().is_err());
+}
+
+#[test]
+fn test_message_actions_for() {
+    // actions for hashes 1, 2, 1 and 3, with script types 0 to 3
+    let actions = [1u8, 2, 1, 3]
+        .into_iter()
+        .enumerate()
+        .map(|(i, hash)| {
+            Action::new_builder()
+                .script_hash([hash; 32].pack())
+                .script_type((i as u8).into())
+                .build()
+        })
+        .collect::<Vec<_>>();
+    let message = Message::new_builder()
+        .actions(ActionVec::new_builder().set(actions).build())
+        .build();
+    let message = lazy_basic::Message::from(new_cursor_from_vec(message.as_slice().to_vec()));
+
+    let script_types = |hash: u8| {
+        message
+            .actions_for(&[hash; 32])
+            .unwrap()
+            .iter()
+            .map(|action| action.script_type().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(script_types(1), vec![0, 2]);
+    assert_eq!(script_types(2), vec![1]);
+    assert_eq!(script_types(3), vec![3]);
+    assert!(script_types(4).is_empty());
+}
+
+#[test]
+fn test_validate_actions() {
+    let lock_hash = [1u8; 32];
+    let absent_hash = [2u8; 32];
+    let mut cache = BTreeMap::new();
+    cache.insert(
+        lock_hash,
+        ScriptLocation {
+            input_lock: vec![0],
+            input_type: vec![],
+            output_type: vec![],
+            output_lock: vec![],
+            cell_dep_lock: vec![],
+            cell_dep_type: vec![],
+        },
+    );
+    let action = |script_hash: [u8; 32], script_type: u8| {
+        Action::new_builder()
+            .script_hash(script_hash.pack())
+            .script_type(script_type.into())
+            .build()
+    };
+    let lazy_message = |actions: Vec<Action>| {
+        let message = Message::new_builder()
+            .actions(ActionVec::new_builder().set(actions).build())
+            .build();
+        lazy_basic::Message::from(new_cursor_from_vec(message.as_slice().to_vec()))
+    };
+
+    let message = lazy_message(vec![action(lock_hash, 0), action(lock_hash, 0)]);
+    assert!(validate_actions(&cache, &message).is_ok());
+
+    // the second action refers to an absent script
+    let message = lazy_message(vec![action(lock_hash, 0), action(absent_hash, 0)]);
+    assert!(matches!(
+        validate_actions(&cache, &message),
+        Err((1, CobuildError::ScriptHashAbsent))
+    ));
+
+    // the first action has a wrong script type
+    let message = lazy_message(vec![action(lock_hash, 7), action(lock_hash, 0)]);
+    assert!(matches!(
+        validate_actions(&cache, &message),
+        Err((0, CobuildError::WrongScriptType))
+    ));
+
+    // both scripts exist, but only actions naming the current one are valid
+    let other_hash = [3u8; 32];
+    cache.insert(
+        other_hash,
+        ScriptLocation {
+            input_lock: vec![1],
+            ..Default::default()
+        },
+    );
+    let message = lazy_message(vec![action(lock_hash, 0), action(other_hash, 0)]);
+    assert!(validate_actions(&cache, &message).is_ok());
+    assert!(matches!(
+        check_message_self(&cache, message.clone(), &lock_hash),
+        Err(CobuildError::ScriptHashAbsent)
+    ));
+    let message = lazy_message(vec![action(lock_hash, 0)]);
+    assert!(check_message_self(&cache, message, &lock_hash).is_ok());
+}
+
+#[test]
+fn test_action_locations() {
+    // the same hash used as input lock and output type
+    let hash = [1u8; 32];
+    let location = ScriptLocation {
+        input_lock: vec![0, 2],
+        output_type: vec![1],
+        ..Default::default()
+    };
+    let mut cache = BTreeMap::new();
+    cache.insert(hash, location.clone());
+    let lazy_action = |script_hash: [u8; 32], script_type: u8| {
+        let action = Action::new_builder()
+            .script_hash(script_hash.pack())
+            .script_type(script_type.into())
+            .build();
+        lazy_basic::Action::from(new_cursor_from_vec(action.as_slice().to_vec()))
+    };
+
+    // the locations don't depend on the declared script type
+    for script_type in [0, 2, 1] {
+        assert_eq!(
+            action_locations(&cache, &lazy_action(hash, script_type)).unwrap(),
+            location
+        );
+    }
+    // an input type is declared, which the hash isn't used as
+    assert!(!is_script_exist(&cache, hash, ScriptType::InputType));
+    assert!(matches!(
+        action_locations(&cache, &lazy_action([2u8; 32], 0)),
+        Err(CobuildError::ScriptHashAbsent)
+    ));
+}
+
+#[test]
+fn test_assert_single_lock_instance() {
+    let hash = [1u8; 32];
+    let mut cache = BTreeMap::new();
+    cache.insert(
+        hash,
+        ScriptLocation {
+            input_lock: vec![0, 2],
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        assert_single_lock_instance(&cache, &hash),
+        Err(CobuildError::WrongCount)
+    ));
+
+    // outputs and input types aren't counted
+    cache.insert(
+        hash,
+        ScriptLocation {
+            input_lock: vec![1],
+            input_type: vec![0],
+            output_lock: vec![0, 1],
+            ..Default::default()
+        },
+    );
+    assert!(assert_single_lock_instance(&cache, &hash).is_ok());
+    assert!(matches!(
+        assert_single_lock_instance(&cache, &[2u8; 32]),
+        Err(CobuildError::WrongCount)
+    ));
+}
+
+#[test]
+fn test_cobuild_state_encoding() {
+    let state = CobuildState {
+        otx_start_index: 3,
+        input_start: 0,
+        input_end: 1,
+        output_end: 2,
+        cell_dep_end: 0,
+        header_dep_end: 0,
+    };
+    assert_eq!(state.to_string(), "is=0 ie=1 oe=2 cde=0 hde=0 osi=3");
+    let mut expected = [0u8; 24];
+    expected[4] = 1;
+    expected[8] = 2;
+    expected[20] = 3;
+    assert_eq!(state.to_bytes(), Some(expected));
+    assert_eq!(CobuildState::from_bytes(&expected), state);
+}
+
+#[test]
+fn test_cobuild_state_encoding_boundary() {
+    let state = CobuildState {
+        otx_start_index: u32::MAX as usize,
+        input_start: u32::MAX,
+        input_end: u32::MAX,
+        output_end: u32::MAX,
+        cell_dep_end: u32::MAX,
+        header_dep_end: u32::MAX,
+    };
+    let bytes = state.to_bytes().unwrap();
+    assert_eq!(bytes, [0xff; 24]);
+    assert_eq!(CobuildState::from_bytes(&bytes), state);
+
+    // used to be truncated to 0
+    let state = CobuildState {
+        otx_start_index: u32::MAX as usize + 1,
+        ..state
+    };
+    assert_eq!(state.to_bytes(), None);
+}
+
+#[test]
+fn test_ct_eq() {
+    let hash = [1u8; 32];
+    let mut other = hash;
+    assert!(ct_eq_32(&hash, &other));
+    other[31] = 2;
+    assert!(!ct_eq_32(&hash, &other));
+    assert!(!ct_eq_32(&hash[..31], &hash));
+    assert!(ct_eq(&[], &[]));
+    assert!(!ct_eq(&[1, 2], &[1, 2, 3]));
+}
+
+#[test]
+fn test_unsupported_witness_layout_id() {
+    // a member appended to WitnessLayout by a newer specification
+    let mut unknown = 0xFF00_0005u32.to_le_bytes().to_vec();
+    unknown.extend_from_slice(&[0u8; 8]);
+    assert_eq!(
+        unsupported_witness_layout_id(&new_cursor_from_vec(unknown)),
+        Some(0xFF00_0005)
+    );
+
+    let sighash_all_only = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAllOnly(
+            SighashAllOnly::new_builder().build(),
+        ))
+        .build();
+    assert_eq!(
+        unsupported_witness_layout_id(&new_cursor_from_vec(sighash_all_only.as_slice().to_vec())),
+        None
+    );
+    let witness_args = WitnessArgs::new_builder().build();
+    assert_eq!(
+        unsupported_witness_layout_id(&new_cursor_from_vec(witness_args.as_slice().to_vec())),
+        None
+    );
+}
+
+#[test]
+fn test_any_witness_layout() {
+    let mut unknown = 0xFF00_0005u32.to_le_bytes().to_vec();
+    unknown.extend_from_slice(&[0u8; 8]);
+    let parsed = AnyWitnessLayout::parse(new_cursor_from_vec(unknown)).unwrap();
+    assert_eq!(parsed.unknown_id(), Some(0xFF00_0005));
+    assert!(parsed.known().is_none());
+
+    let sighash_all_only = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAllOnly(
+            SighashAllOnly::new_builder().build(),
+        ))
+        .build();
+    let parsed =
+        AnyWitnessLayout::parse(new_cursor_from_vec(sighash_all_only.as_slice().to_vec())).unwrap();
+    assert_eq!(parsed.unknown_id(), None);
+    assert!(matches!(
+        parsed.known(),
+        Some(lazy_top_level::WitnessLayout::SighashAllOnly(_))
+    ));
+
+    let witness_args = WitnessArgs::new_builder().build();
+    assert!(
+        AnyWitnessLayout::parse(new_cursor_from_vec(witness_args.as_slice().to_vec())).is_none()
+    );
+    assert!(AnyWitnessLayout::parse(new_cursor_from_vec(vec![])).is_none());
+}
+
+#[test]
+fn test_action_and_message_digest() {
+    let lazy_action = |data: &[u8]| {
+        let action = Action::new_builder()
+            .script_hash([1u8; 32].pack())
+            .script_type(1u8.into())
+            .data(Bytes::from(data.to_vec()).pack())
+            .build();
+        lazy_basic::Action::from(new_cursor_from_vec(action.as_slice().to_vec()))
+    };
+    let mint = lazy_action(b"MINT").action_digest().unwrap();
+    let burn = lazy_action(b"BURN").action_digest().unwrap();
+    assert_eq!(mint, lazy_action(b"MINT").action_digest().unwrap());
+    assert_ne!(mint, burn);
+    // dedup
+    let digests: std::collections::BTreeSet<[u8; 32]> = [mint, burn, mint].into_iter().collect();
+    assert_eq!(digests.len(), 2);
+
+    let message = Message::new_builder().build();
+    let lazy_message = lazy_basic::Message::from(new_cursor_from_vec(message.as_slice().to_vec()));
+    let message_digest = lazy_message.message_digest().unwrap();
+    let mut hasher = ckb_testtool::ckb_hash::Blake2bBuilder::new(32)
+        .personal(b"ckb-tcob-digest")
+        .build();
+    hasher.update(message.as_slice());
+    let mut expected = [0u8; 32];
+    hasher.finalize(&mut expected);
+    assert_eq!(message_digest, expected);
+}
+
+#[test]
+fn test_parse_witness_layout_bytes() {
+    let witness = WitnessLayout::new_builder()
+        .set(WitnessLayoutUnion::SighashAllOnly(
+            SighashAllOnly::new_builder()
+                .seal(Bytes::from(vec![1u8; 65]).pack())
+                .build(),
+        ))
+        .build()
+        .as_bytes();
+    assert!(parse_witness_layout_bytes(&witness).is_ok());
+    assert!(parse_witness_layout_bytes(WitnessArgs::default().as_slice()).is_err());
+
+    // malformed input is rejected without panic
+    for len in 0..witness.len() {
+        assert!(parse_witness_layout_bytes(&witness[..len]).is_err());
+    }
+    let mut rng = thread_rng();
+    for _ in 0..1000 {
+        let mut data = witness.to_vec();
+        let index = rng.next_u32() as usize % data.len();
+        data[index] = rng.next_u32() as u8;
+        let _ = parse_witness_layout_bytes(&data);
+    }
+}
+
+#[test]
+fn test_success_otx_multiple_segments() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 2);
+    let message = Message::new_builder().build();
+
+    // each segment contains one OTX with one input and one output
+    let (otx0, otx0_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 0..1, 0..1);
+    let smh0 = generate_otx_signing_message_hash(&message, &otx0, &otx0_inputs);
+    let (otx1, otx1_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 1..2, 1..2);
+    let smh1 = generate_otx_signing_message_hash(&message, &otx1, &otx1_inputs);
+
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![otx_seal_pair(&privkey, &lock_script, &smh0)],
+        ),
+        // segments are separated by a non-OTX witness
+        Bytes::new(),
+        otx_start_witness([1, 1, 0, 0]),
+        otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message,
+            vec![otx_seal_pair(&privkey, &lock_script, &smh1)],
+        ),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+// The second OTX carries a placeholder seal, which the lock demo rejects.
+// cobuild_entry_partial skips it and reports the OTX as unsigned.
+#[test]
+fn test_otx_partially_signed() {
+    let (context, privkey, lock_script, tx, resolved_inputs) = gen_otx_tx(2, 2);
+    let message = Message::new_builder().build();
+    let (otx0, otx0_inputs) = otx_range(&tx.data().raw(), &resolved_inputs, 0..1, 0..1);
+    let smh0 = generate_otx_signing_message_hash(&message, &otx0, &otx0_inputs);
+    let placeholder = SealPair::new_builder()
+        .script_hash(lock_script.calc_script_hash())
+        .build();
+
+    let witnesses = vec![
+        otx_start_witness([0, 0, 0, 0]),
+        otx_witness(
+            0,
+            [1, 1, 0, 0],
+            [0, 0, 0, 0],
+            message.clone(),
+            vec![otx_seal_pair(&privkey, &lock_script, &smh0)],
+        ),
+        otx_witness(0, [1, 1, 0, 0], [0, 0, 0, 0], message, vec![placeholder]),
+    ];
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect())
+        .build();
+
+    let err = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect_err("fail verification");
+    assert_script_error(err, 5); // return Error::AuthError
+
+    set_mock_transaction(MockTransaction::new(
+        tx.data(),
+        resolved_inputs,
+        lock_script,
+    ));
+    let mut recorder = SmhRecorder::default();
+    let result = cobuild_entry_partial(&mut recorder).unwrap();
+    assert_eq!(
+        result,
+        PartialCobuildResult {
+            result: CobuildResult {
+                activated: true,
+                execution_count: 1,
+                otx_count: 2,
+                normal_entry_invoked: false,
+            },
+            unsigned_otx_indices: vec![2],
+        }
+    );
+    assert_eq!(recorder.0, vec![smh0]);
 }
 
 // generated unit test for contract transaction-cobuild-type-otx-demo
@@ -7,7 +7,7 @@ use ckb_testtool::ckb_types::{
     prelude::*,
 };
 use ckb_testtool::context::Context;
-use ckb_transaction_cobuild::blake2b::{new_sighash_all_blake2b, new_sighash_all_only_blake2b};
+use ckb_transaction_cobuild::host;
 use ckb_transaction_cobuild::schemas::{
     basic::{Action, ActionVec, Message, ResolvedInputs, SighashAll, SighashAllOnly},
     blockchain,
@@ -179,8 +179,7 @@ impl MessageWitnesses {
     }
 
     pub fn rng_bytes(len: usize) -> blockchain::Bytes {
-        let mut buf = Vec::with_capacity(len);
-        buf.resize(len, 0);
+        let mut buf = vec![0; len];
 
         thread_rng().fill_bytes(&mut buf);
 
@@ -276,37 +275,7 @@ fn generate_signing_message_hash(
     tx: &TransactionView,
     resolved_inputs: &ResolvedInputs,
 ) -> [u8; 32] {
-    // message
-    let mut hasher = match message {
-        Some(m) => {
-            let mut hasher = new_sighash_all_blake2b();
-            hasher.update(m.as_slice());
-            hasher
-        }
-        None => new_sighash_all_only_blake2b(),
-    };
-    // tx hash
-    hasher.update(tx.hash().as_slice());
-    // inputs cell and data
-    let inputs_len = tx.inputs().len();
-    debug_assert!(inputs_len == resolved_inputs.outputs().len());
-    debug_assert!(inputs_len == resolved_inputs.outputs_data().len());
-    for i in 0..inputs_len {
-        let input_cell = resolved_inputs.outputs().get(i).unwrap();
-        hasher.update(input_cell.as_slice());
-        let input_cell_data = resolved_inputs.outputs_data().get(i).unwrap();
-        hasher.update(&(input_cell_data.len() as u32).to_le_bytes());
-        hasher.update(&input_cell_data.raw_data());
-    }
-    // extra witnesses
-    for witness in tx.witnesses().into_iter().skip(inputs_len) {
-        hasher.update(&(witness.len() as u32).to_le_bytes());
-        hasher.update(&witness.raw_data());
-    }
-
-    let mut result = [0u8; 32];
-    hasher.finalize(&mut result);
-    result
+    host::signing_message_hash(message, &tx.data(), resolved_inputs).unwrap()
 }
 
 fn witness_is_empty(tx: &TransactionView, index: usize) -> bool {